@@ -0,0 +1,113 @@
+/* Union find benchmark
+ * Times the four strategies on growing sizes and prints CSV, one row
+ * per run, to plot how the cost per operation grows with n:
+ *   strategy,sequence,n,unions,ops,ns_per_op,max_depth
+ * Each run does its sequence's unions and then n connected queries,
+ * unless it runs out of its time budget first; ops says how many it
+ * got through, since quick_find and quick_union can't finish the
+ * large sizes. Random, Chain and Binomial do n - 1 unions; Binomial
+ * is the one that makes the balanced trees lg n deep. Grid only uses
+ * the side * side elements of the largest square that fits in n,
+ * with 2 * side * (side - 1) unions between neighbours, so compare
+ * rows by unions as well as n. Run it with
+ *   cargo run --release --bin union_find_bench [max_n] [budget_ms]
+ */
+
+#[path = "../util.rs"]
+#[allow(dead_code)]
+mod util;
+#[path = "../union_find/mod.rs"]
+#[allow(dead_code)]
+mod union_find;
+
+use std::env;
+use std::time::Instant;
+
+use union_find::DisjointSet;
+use union_find::b_quick_union::BalancedQuickUnion;
+use union_find::pc_quick_union::CompressedQuickUnion;
+use union_find::quick_find::QuickFind;
+use union_find::quick_union::QuickUnion;
+
+#[derive(Debug, Copy, Clone)]
+enum Sequence {
+    Random,
+    Chain,    // union(0, i): quick_union walks the whole chain every time
+    Binomial, // pairwise merges: lg n deep even for the balanced ones
+    Grid,     // neighbours on a square grid, row by row
+}
+
+fn unions(sequence: Sequence, n: usize) -> Vec<(usize, usize)> {
+    match sequence {
+        Sequence::Random => (1..n).map(|_| (util::rand_upto(n), util::rand_upto(n))).collect(),
+        Sequence::Chain => (1..n).map(|i| (0, i)).collect(),
+        Sequence::Binomial => union_find::binomial(n),
+        Sequence::Grid => {
+            let side = (n as f64).sqrt() as usize;
+            let mut out = Vec::with_capacity(n);
+            for row in 0..side {
+                for col in 0..side {
+                    let i = row * side + col;
+                    if col + 1 < side {
+                        out.push((i, i + 1));
+                    }
+                    if row + 1 < side {
+                        out.push((i, i + side));
+                    }
+                }
+            }
+            out
+        },
+    }
+}
+
+/// (ops done, ns per op, max depth)
+fn run<S: DisjointSet>(n: usize, unions: &[(usize, usize)], queries: &[(usize, usize)],
+                       budget_ms: u128) -> (usize, f64, usize) {
+    let mut sets = S::new(n);
+    let ops = unions.iter().map(|&(a, b)| (a, b, true))
+        .chain(queries.iter().map(|&(a, b)| (a, b, false)));
+    let start = Instant::now();
+    let mut done: usize = 0;
+    for (a, b, union) in ops {
+        if union {
+            sets.union(a, b);
+        } else {
+            sets.connected(a, b);
+        }
+        done += 1;
+        // Checking the clock is slow too, so only now and then: at
+        // powers of two, for the slow runs, and then every 1024
+        let check = done.is_power_of_two() || done.is_multiple_of(1024);
+        if check && start.elapsed().as_millis() > budget_ms {
+            break;
+        }
+    }
+    let ns = start.elapsed().as_nanos() as f64 / done.max(1) as f64;
+    (done, ns, sets.max_depth())
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    let max_n: usize = args.get(1).map_or(10_000_000, |s| s.parse().expect("max_n"));
+    let budget_ms: u128 = args.get(2).map_or(2_000, |s| s.parse().expect("budget_ms"));
+
+    println!("strategy,sequence,n,unions,ops,ns_per_op,max_depth");
+    let mut n = 1_000;
+    while n <= max_n {
+        for sequence in [Sequence::Random, Sequence::Chain, Sequence::Binomial, Sequence::Grid].iter() {
+            let unions = unions(*sequence, n);
+            let queries: Vec<(usize, usize)> =
+                (0..n).map(|_| (util::rand_upto(n), util::rand_upto(n))).collect();
+            let runs = [("quick_find", run::<QuickFind> as fn(_, &_, &_, _) -> _),
+                        ("quick_union", run::<QuickUnion>),
+                        ("b_quick_union", run::<BalancedQuickUnion>),
+                        ("pc_quick_union", run::<CompressedQuickUnion>)];
+            for &(name, f) in runs.iter() {
+                let (ops, ns, depth) = f(n, &unions, &queries, budget_ms);
+                println!("{},{:?},{},{},{},{:.1},{}", name, sequence, n, unions.len(), ops, ns, depth);
+            }
+        }
+        n *= 10;
+    }
+}
@@ -0,0 +1,331 @@
+/* Saving and loading BinaryTree contents
+ * Both formats write the entries in sorted order (an in-order walk)
+ * and load them back with from_sorted_iter, so a tree comes back
+ * perfectly balanced whatever shape it had when it was saved.
+ *
+ * The binary format is:
+ *   magic   4 bytes  "BTRE"
+ *   version 1 byte   currently 1
+ *   count   u32      number of entries
+ *   entries          key then value, count times, keys increasing
+ * with integers in little endian and strings as a u32 byte length
+ * followed by UTF-8, so saving a string of 4 GiB or more fails.
+ */
+#![allow(dead_code)]
+
+use std::convert::TryFrom;
+use std::fmt;
+use std::mem;
+
+use super::BinaryTree;
+
+const MAGIC: &[u8; 4] = b"BTRE";
+const VERSION: u8 = 1;
+
+#[derive(Debug, PartialEq)]
+pub enum DecodeError {
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    InvalidUtf8,
+    Unsorted, // keys out of order or repeated
+    TrailingBytes,
+    TooLarge(u64), // a usize saved on a bigger machine
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            DecodeError::BadMagic => write!(f, "not a BinaryTree file"),
+            DecodeError::UnsupportedVersion(v) => write!(f, "unsupported format version {}", v),
+            DecodeError::Truncated => write!(f, "input ends in the middle of an entry"),
+            DecodeError::InvalidUtf8 => write!(f, "string is not valid UTF-8"),
+            DecodeError::Unsorted => write!(f, "keys are not strictly increasing"),
+            DecodeError::TrailingBytes => write!(f, "unexpected bytes after the last entry"),
+            DecodeError::TooLarge(x) => write!(f, "{} does not fit in a usize here", x),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EncodeError {
+    TooLarge(usize), // a length the format has no room for
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            EncodeError::TooLarge(len) => write!(f, "{} bytes is too long to save", len),
+        }
+    }
+}
+
+/// Types that can be written to and read back from the binary format
+pub trait Codec: Sized {
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), EncodeError>;
+    /// Reads a value from the front of input, advancing it
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError>;
+}
+
+fn take<'a>(input: &mut &'a [u8], n: usize) -> Result<&'a [u8], DecodeError> {
+    if input.len() < n {
+        return Err(DecodeError::Truncated);
+    }
+    let (head, rest) = input.split_at(n);
+    *input = rest;
+    Ok(head)
+}
+
+macro_rules! int_codec {
+    ($($t:ty),*) => {
+        $(
+            impl Codec for $t {
+                fn encode(&self, out: &mut Vec<u8>) -> Result<(), EncodeError> {
+                    out.extend_from_slice(&self.to_le_bytes());
+                    Ok(())
+                }
+                fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+                    let mut bytes = [0; mem::size_of::<$t>()];
+                    bytes.copy_from_slice(take(input, mem::size_of::<$t>())?);
+                    Ok(<$t>::from_le_bytes(bytes))
+                }
+            }
+        )*
+    }
+}
+
+int_codec!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+impl Codec for usize {
+    // Always 8 bytes, so files move between 32 and 64 bit machines;
+    // a value too big for this one is an error, not a wrapped key
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), EncodeError> {
+        (*self as u64).encode(out)
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let x = u64::decode(input)?;
+        usize::try_from(x).map_err(|_| DecodeError::TooLarge(x))
+    }
+}
+
+impl Codec for () {
+    fn encode(&self, _: &mut Vec<u8>) -> Result<(), EncodeError> {
+        Ok(())
+    }
+    fn decode(_: &mut &[u8]) -> Result<Self, DecodeError> {
+        Ok(())
+    }
+}
+
+impl Codec for String {
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), EncodeError> {
+        let len = u32::try_from(self.len()).map_err(|_| EncodeError::TooLarge(self.len()))?;
+        len.encode(out)?;
+        out.extend_from_slice(self.as_bytes());
+        Ok(())
+    }
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = u32::decode(input)? as usize;
+        let bytes = take(input, len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8)
+    }
+}
+
+impl<K: Ord + Codec, V: Codec> BinaryTree<K, V> {
+    pub fn to_bytes(&self) -> Result<Vec<u8>, EncodeError> {
+        let mut out = Vec::new();
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        self.count().encode(&mut out)?;
+        for (key, val) in self.iter() {
+            key.encode(&mut out)?;
+            val.encode(&mut out)?;
+        }
+        Ok(out)
+    }
+    /// Rebuilds a balanced tree from the output of to_bytes
+    pub fn from_bytes(mut input: &[u8]) -> Result<Self, DecodeError> {
+        if take(&mut input, MAGIC.len()).map_err(|_| DecodeError::BadMagic)? != MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        let version = u8::decode(&mut input)?;
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let count = u32::decode(&mut input)?;
+        // Don't trust count for the allocation, the entries may be missing
+        let mut entries: Vec<(K, V)> = Vec::new();
+        for _ in 0..count {
+            let key = K::decode(&mut input)?;
+            let val = V::decode(&mut input)?;
+            if entries.last().is_some_and(|last| last.0 >= key) {
+                return Err(DecodeError::Unsorted);
+            }
+            entries.push((key, val));
+        }
+        if !input.is_empty() {
+            return Err(DecodeError::TrailingBytes);
+        }
+        Ok(BinaryTree::from_sorted_iter(entries))
+    }
+}
+
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use serde::de::{Deserialize, Deserializer, MapAccess, Visitor};
+    use serde::ser::{Serialize, SerializeMap, Serializer};
+    use std::fmt;
+    use std::marker::PhantomData;
+
+    use super::super::BinaryTree;
+
+    /// Serializes as a map, with the keys in order
+    impl<K: Ord + Serialize, V: Serialize> Serialize for BinaryTree<K, V> {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            let mut map = serializer.serialize_map(Some(self.count() as usize))?;
+            for (key, val) in self.iter() {
+                map.serialize_entry(key, val)?;
+            }
+            map.end()
+        }
+    }
+
+    struct TreeVisitor<K, V> {
+        marker: PhantomData<(K, V)>,
+    }
+
+    impl<'de, K: Ord + Deserialize<'de>, V: Deserialize<'de>> Visitor<'de> for TreeVisitor<K, V> {
+        type Value = BinaryTree<K, V>;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a map")
+        }
+
+        fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+            let mut entries: Vec<(K, V)> = Vec::with_capacity(access.size_hint().unwrap_or(0));
+            while let Some(entry) = access.next_entry()? {
+                entries.push(entry);
+            }
+            // Maps written by hand may be out of order or repeat keys:
+            // sort (a no-op pass on our own output) and let the last
+            // copy of a key win, like repeated puts would.
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            let mut sorted: Vec<(K, V)> = Vec::with_capacity(entries.len());
+            for entry in entries {
+                if sorted.last().is_some_and(|last| last.0 == entry.0) {
+                    sorted.pop();
+                }
+                sorted.push(entry);
+            }
+            Ok(BinaryTree::from_sorted_iter(sorted))
+        }
+    }
+
+    /// Deserializes a map into a balanced tree
+    impl<'de, K: Ord + Deserialize<'de>, V: Deserialize<'de>> Deserialize<'de> for BinaryTree<K, V> {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_map(TreeVisitor { marker: PhantomData })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DecodeError;
+    use super::super::BinaryTree;
+    use util;
+
+    fn random_tree(n: usize) -> BinaryTree<u32, String> {
+        util::rand_vec(n).into_iter()
+            .map(|x| (x as u32, "v".repeat(x % 7)))
+            .collect()
+    }
+
+    fn assert_same<K: Ord + ::std::fmt::Debug, V: PartialEq + ::std::fmt::Debug>(a: &BinaryTree<K, V>,
+                                                                              b: &BinaryTree<K, V>) {
+        assert!(a.iter().eq(b.iter()));
+        assert_eq!(Ok(()), b.check_invariants());
+        // loading always rebuilds a perfectly balanced tree
+        assert_eq!(32 - b.count().leading_zeros(), b.height());
+    }
+
+    #[test]
+    fn bytes_round_trip() {
+        for n in 0..60 {
+            let tree = random_tree(n);
+            let bytes = tree.to_bytes().unwrap();
+            let loaded: BinaryTree<u32, String> = BinaryTree::from_bytes(&bytes).unwrap();
+            assert_same(&tree, &loaded);
+            assert_eq!(bytes, loaded.to_bytes().unwrap());
+        }
+        let mut set = BinaryTree::new();
+        for k in [-3i64, 7, 0, 12].iter() {
+            set.put(*k, ());
+        }
+        // header, count, and 8 bytes per key
+        assert_eq!(4 + 1 + 4 + 4 * 8, set.to_bytes().unwrap().len());
+        let loaded: BinaryTree<i64, ()> = BinaryTree::from_bytes(&set.to_bytes().unwrap()).unwrap();
+        assert_same(&set, &loaded);
+    }
+
+    #[test]
+    fn bad_input() {
+        let mut tree = BinaryTree::new();
+        tree.put("b".to_string(), 2usize);
+        tree.put("a".to_string(), 1usize);
+        let bytes = tree.to_bytes().unwrap();
+        type T = BinaryTree<String, usize>;
+        assert_eq!(Some(&b"BTRE\x01"[..]), bytes.get(0..5));
+        assert_eq!(Err(DecodeError::BadMagic), T::from_bytes(b"BTR").map(|_| ()));
+        assert_eq!(Err(DecodeError::BadMagic), T::from_bytes(b"XTRE\x01").map(|_| ()));
+        let mut future = bytes.clone();
+        future[4] = 2;
+        assert_eq!(Err(DecodeError::UnsupportedVersion(2)), T::from_bytes(&future).map(|_| ()));
+        for len in 5..bytes.len() {
+            assert_eq!(Err(DecodeError::Truncated), T::from_bytes(&bytes[..len]).map(|_| ()));
+        }
+        let mut longer = bytes.clone();
+        longer.push(0);
+        assert_eq!(Err(DecodeError::TrailingBytes), T::from_bytes(&longer).map(|_| ()));
+        // swap "a" and "b" so the keys come out of order
+        let mut swapped = bytes.clone();
+        let a = bytes.iter().position(|c| *c == b'a').unwrap();
+        let b = bytes.iter().position(|c| *c == b'b').unwrap();
+        swapped.swap(a, b);
+        assert_eq!(Err(DecodeError::Unsorted), T::from_bytes(&swapped).map(|_| ()));
+        let mut not_utf8 = bytes.clone();
+        not_utf8[a] = 0xff;
+        assert_eq!(Err(DecodeError::InvalidUtf8), T::from_bytes(&not_utf8).map(|_| ()));
+        // the last value, 2usize, as saved by a machine with more bits
+        let mut huge = bytes.clone();
+        let last = huge.len() - 8;
+        huge[last..].copy_from_slice(&u64::MAX.to_le_bytes());
+        let loaded = T::from_bytes(&huge).map(|t| t.get(&"b".to_string()).cloned());
+        if cfg!(target_pointer_width = "64") {
+            assert_eq!(Ok(Some(usize::MAX)), loaded);
+        } else {
+            assert_eq!(Err(DecodeError::TooLarge(u64::MAX)), loaded);
+        }
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_round_trip() {
+        use serde_json;
+        for n in 0..60 {
+            let tree = random_tree(n);
+            let json = serde_json::to_string(&tree).unwrap();
+            let loaded: BinaryTree<u32, String> = serde_json::from_str(&json).unwrap();
+            assert_same(&tree, &loaded);
+            assert_eq!(json, serde_json::to_string(&loaded).unwrap());
+        }
+        let mut tree = BinaryTree::new();
+        tree.put(2, vec!["two"]);
+        tree.put(1, vec!["one"]);
+        assert_eq!(r#"{"1":["one"],"2":["two"]}"#, serde_json::to_string(&tree).unwrap());
+        // out of order and repeated keys load like a sequence of puts
+        let loaded: BinaryTree<u32, u32> = serde_json::from_str(r#"{"3":0,"1":1,"3":3}"#).unwrap();
+        let entries: Vec<(&u32, &u32)> = loaded.iter().collect();
+        assert_eq!(vec![(&1, &1), (&3, &3)], entries);
+    }
+}
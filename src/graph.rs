@@ -0,0 +1,189 @@
+/* Undirected graphs as a list of weighted edges
+ * Everything here only asks whether an edge joins two vertices that
+ * are already connected, which is what union find answers: Kruskal
+ * takes the edges from lightest to heaviest and keeps the ones that
+ * join two components, the components are the union find's sets, and
+ * an edge inside a component closes a cycle.
+ */
+#![allow(dead_code)]
+
+use sort;
+use union_find::DisjointSet;
+use union_find::pc_quick_union::CompressedQuickUnion;
+
+/// Fields in this order so edges sort by weight first
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Edge {
+    pub weight: i64,
+    pub from: usize,
+    pub to: usize,
+}
+
+/// Vertices are 0..n
+pub struct Graph {
+    n: usize,
+    edges: Vec<Edge>,
+}
+
+impl Graph {
+    pub fn new(n: usize) -> Self {
+        Graph { n, edges: Vec::new() }
+    }
+    pub fn vertices(&self) -> usize {
+        self.n
+    }
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+    pub fn add_edge(&mut self, from: usize, to: usize, weight: i64) {
+        assert!(from < self.n && to < self.n, "vertex out of range");
+        self.edges.push(Edge { weight, from, to });
+    }
+    fn sets(&self) -> CompressedQuickUnion {
+        let mut sets = CompressedQuickUnion::new(self.n);
+        for e in self.edges.iter() {
+            sets.union(e.from, e.to);
+        }
+        sets
+    }
+    /// Kruskal's minimum spanning forest: its edges, lightest first,
+    /// and their total weight. A disconnected graph gets a tree per
+    /// component.
+    pub fn mst(&self) -> (Vec<Edge>, i64) {
+        let mut edges = self.edges.clone();
+        sort::shell(&mut edges);
+        let mut sets = CompressedQuickUnion::new(self.n);
+        let mut tree = Vec::new();
+        let mut weight = 0;
+        for e in edges {
+            // A tree has n - 1 edges, the rest can't join anything
+            if tree.len() + 1 == self.n {
+                break;
+            }
+            if sets.union(e.from, e.to) {
+                weight += e.weight;
+                tree.push(e);
+            }
+        }
+        (tree, weight)
+    }
+    /// Label of each vertex's component, numbered from 0 in order of
+    /// their smallest vertex
+    pub fn components(&self) -> Vec<usize> {
+        let mut labels = vec![0; self.n];
+        for (label, group) in self.sets().groups().iter().enumerate() {
+            for v in group.iter() {
+                labels[*v] = label;
+            }
+        }
+        labels
+    }
+    pub fn count_components(&self) -> usize {
+        self.sets().count()
+    }
+    /// Self loops and parallel edges count as cycles
+    pub fn has_cycle(&self) -> bool {
+        let mut sets = CompressedQuickUnion::new(self.n);
+        self.edges.iter().any(|e| !sets.union(e.from, e.to))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Edge, Graph};
+    use util;
+
+    fn graph(n: usize, edges: &[(usize, usize, i64)]) -> Graph {
+        let mut g = Graph::new(n);
+        for &(from, to, weight) in edges.iter() {
+            g.add_edge(from, to, weight);
+        }
+        g
+    }
+
+    #[test]
+    fn tiny_ewg() {
+        // tinyEWG.txt from Sedgewick & Wayne's Algorithms, 4.3
+        let g = graph(8, &[(4, 5, 35), (4, 7, 37), (5, 7, 28), (0, 7, 16),
+                           (1, 5, 32), (0, 4, 38), (2, 3, 17), (1, 7, 19),
+                           (0, 2, 26), (1, 2, 36), (1, 3, 29), (2, 7, 34),
+                           (6, 2, 40), (3, 6, 52), (6, 0, 58), (6, 4, 93)]);
+        let (tree, weight) = g.mst();
+        assert_eq!(181, weight);
+        let expected = [(0, 7, 16), (2, 3, 17), (1, 7, 19), (0, 2, 26),
+                        (5, 7, 28), (4, 5, 35), (6, 2, 40)];
+        let edges: Vec<(usize, usize, i64)> = tree.iter().map(|e| (e.from, e.to, e.weight)).collect();
+        assert_eq!(expected.to_vec(), edges);
+        assert_eq!(1, g.count_components());
+        assert!(g.has_cycle());
+    }
+
+    #[test]
+    fn forests() {
+        //  0 - 1   2   3 - 4
+        //       \         |
+        //        5        6
+        let g = graph(7, &[(0, 1, 4), (1, 5, -2), (3, 4, 1), (4, 6, 0)]);
+        assert_eq!(vec![0, 0, 1, 2, 2, 0, 2], g.components());
+        assert_eq!(3, g.count_components());
+        assert!(!g.has_cycle());
+        let (tree, weight) = g.mst();
+        assert_eq!(4, tree.len());
+        assert_eq!(3, weight);
+        assert_eq!(Edge { weight: -2, from: 1, to: 5 }, tree[0]);
+
+        let mut looped = graph(2, &[(0, 1, 1)]);
+        looped.add_edge(1, 1, 5);
+        assert!(looped.has_cycle());
+        assert_eq!(1, looped.mst().1);
+        assert!(graph(2, &[(0, 1, 1), (1, 0, 2)]).has_cycle());
+
+        let empty = Graph::new(0);
+        assert_eq!((Vec::new(), 0), empty.mst());
+        assert!(empty.components().is_empty());
+        assert!(!empty.has_cycle());
+    }
+
+    #[test]
+    fn mst_is_lightest_spanning_tree() {
+        // Against every spanning tree of small random graphs
+        const N: usize = 5;
+        for _ in 0..20 {
+            let mut g = Graph::new(N);
+            for from in 0..N {
+                for to in (from + 1)..N {
+                    if util::rand_upto(3) != 0 {
+                        g.add_edge(from, to, util::rand_upto(20) as i64);
+                    }
+                }
+            }
+            let m = g.edges().len();
+            let trees = g.vertices() - g.count_components();
+            let mut best = None;
+            for mask in 0..(1u32 << m) {
+                if mask.count_ones() as usize != trees {
+                    continue;
+                }
+                let mut sub = Graph::new(N);
+                for (i, e) in g.edges().iter().enumerate() {
+                    if mask & (1 << i) != 0 {
+                        sub.add_edge(e.from, e.to, e.weight);
+                    }
+                }
+                if !sub.has_cycle() {
+                    let weight: i64 = sub.edges().iter().map(|e| e.weight).sum();
+                    best = Some(best.map_or(weight, |b: i64| b.min(weight)));
+                }
+            }
+            let (tree, weight) = g.mst();
+            assert_eq!(trees, tree.len());
+            assert_eq!(best.unwrap_or(0), weight);
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn edge_out_of_range() {
+        Graph::new(3).add_edge(0, 3, 1);
+    }
+}
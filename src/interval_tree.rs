@@ -0,0 +1,216 @@
+/* Interval tree
+ * Half-open intervals [start, end) kept in a RedBlackTree keyed by
+ * (start, end). Like n, every node also carries an augmented field,
+ * max: the largest end point anywhere in its subtree. A subtree whose
+ * max is <= lo cannot hold anything that overlaps [lo, hi), and that
+ * is what lets the queries skip most of the tree. The red-black tree
+ * recomputes max with n whenever a rotation moves subtrees around.
+ */
+#![allow(dead_code)]
+
+use red_black::{Augment, Link, Node, RedBlackTree};
+
+/// Largest end point in a subtree
+pub struct MaxEnd<T> {
+    max: T,
+}
+
+impl<T: Ord + Clone> Augment<(T, T)> for MaxEnd<T> {
+    fn update(key: &(T, T), left: Option<&Self>, right: Option<&Self>) -> Self {
+        let mut max = &key.1;
+        for child in left.iter().chain(right.iter()) {
+            if child.max > *max {
+                max = &child.max;
+            }
+        }
+        MaxEnd { max: max.clone() }
+    }
+}
+
+type IntervalNode<T, V> = Node<(T, T), V, MaxEnd<T>>;
+
+fn overlaps<T: Ord + Clone, V>(node: &IntervalNode<T, V>, lo: &T, hi: &T) -> bool {
+    node.key.0 < *hi && *lo < node.key.1
+}
+
+fn max_end<T: Ord + Clone, V>(link: &Link<(T, T), V, MaxEnd<T>>) -> Option<&T> {
+    match *link {
+        None => None,
+        Some(ref node) => Some(&node.aug.max),
+    }
+}
+
+pub struct IntervalTree<T: Ord + Clone, V> {
+    tree: RedBlackTree<(T, T), V, MaxEnd<T>>,
+}
+
+impl<T: Ord + Clone, V> IntervalTree<T, V> {
+    pub fn new() -> Self {
+        IntervalTree { tree: RedBlackTree::augmented() }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+    pub fn count(&self) -> u32 {
+        self.tree.count()
+    }
+    /// Adds [start, end), replacing the value if it was already there
+    pub fn insert(&mut self, start: T, end: T, val: V) {
+        assert!(start < end, "empty interval");
+        self.tree.put((start, end), val);
+    }
+    pub fn get(&self, start: &T, end: &T) -> Option<&V> {
+        self.tree.get(&(start.clone(), end.clone()))
+    }
+    pub fn delete(&mut self, start: &T, end: &T) {
+        self.tree.delete(&(start.clone(), end.clone()));
+    }
+    /// Some interval overlapping [lo, hi), in O(lg n)
+    pub fn any_overlap(&self, lo: &T, hi: &T) -> Option<(&T, &T, &V)> {
+        assert!(lo < hi, "empty interval");
+        let mut link = self.tree.root();
+        while let Some(ref node) = *link {
+            if overlaps(node, lo, hi) {
+                return Some((&node.key.0, &node.key.1, &node.val));
+            }
+            // If the left subtree reaches past lo but holds no overlap,
+            // all its intervals start at or after hi, and so does
+            // everything to the right: going left is always safe then.
+            link = match max_end(&node.left) {
+                Some(max) if *max > *lo => &node.left,
+                _ => &node.right,
+            };
+        }
+        None
+    }
+    /// Every interval overlapping [lo, hi), sorted by start
+    pub fn all_overlaps(&self, lo: &T, hi: &T) -> Vec<(&T, &T, &V)> {
+        assert!(lo < hi, "empty interval");
+        // In-order walk that skips subtrees ending at or before lo and
+        // stops at the first start at or past hi
+        let mut out = Vec::new();
+        let mut stack = Vec::new();
+        let mut link = self.tree.root();
+        loop {
+            while let Some(ref node) = *link {
+                if node.aug.max <= *lo {
+                    break;
+                }
+                stack.push(&**node);
+                link = &node.left;
+            }
+            match stack.pop() {
+                Some(node) if node.key.0 < *hi => {
+                    if overlaps(node, lo, hi) {
+                        out.push((&node.key.0, &node.key.1, &node.val));
+                    }
+                    link = &node.right;
+                },
+                _ => break,
+            }
+        }
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{IntervalTree, MaxEnd};
+    use red_black::Link;
+    use util;
+
+    // Checks the max field of every node against its subtree
+    fn assert_max<V>(link: &Link<(usize, usize), V, MaxEnd<usize>>) -> Option<usize> {
+        match *link {
+            None => None,
+            Some(ref node) => {
+                let max = node.key.1.max(assert_max(&node.left).unwrap_or(0))
+                    .max(assert_max(&node.right).unwrap_or(0));
+                assert_eq!(max, node.aug.max);
+                Some(max)
+            }
+        }
+    }
+
+    #[test]
+    fn reservations() {
+        let mut t = IntervalTree::new();
+        t.insert(9, 11, "standup");
+        t.insert(13, 14, "lunch");
+        t.insert(10, 16, "workshop");
+        t.insert(17, 18, "review");
+        assert_eq!(4, t.count());
+        let hits: Vec<&&str> = t.all_overlaps(&11, &13).into_iter().map(|(_, _, v)| v).collect();
+        assert_eq!(vec![&"workshop"], hits);
+        let hits: Vec<&&str> = t.all_overlaps(&10, &14).into_iter().map(|(_, _, v)| v).collect();
+        assert_eq!(vec![&"standup", &"workshop", &"lunch"], hits);
+        // half-open: back to back reservations do not overlap
+        assert!(t.any_overlap(&16, &17).is_none());
+        assert!(t.any_overlap(&18, &20).is_none());
+        assert_eq!(Some((&17, &18, &"review")), t.any_overlap(&17, &20));
+        t.delete(&10, &16);
+        assert!(t.any_overlap(&11, &13).is_none());
+        assert_eq!(Some(&"lunch"), t.get(&13, &14));
+        assert_eq!(3, t.count());
+    }
+
+    #[test]
+    fn matches_brute_force() {
+        const N: usize = 1000;
+        let mut t = IntervalTree::new();
+        let mut all = Vec::new();
+        for i in 0..300 {
+            let start = util::rand_upto(N);
+            let end = start + 1 + util::rand_upto(50);
+            t.insert(start, end, i);
+            all.retain(|&(s, e, _)| (s, e) != (start, end));
+            all.push((start, end, i));
+            if i % 3 == 0 {
+                let (s, e, _) = all.remove(util::rand_upto(all.len()));
+                t.delete(&s, &e);
+            }
+            assert_max(t.tree.root());
+        }
+        all.sort();
+        assert_eq!(all.len() as u32, t.count());
+        for _ in 0..200 {
+            let lo = util::rand_upto(N);
+            let hi = lo + 1 + util::rand_upto(20);
+            let expected: Vec<(usize, usize, usize)> = all.iter().cloned()
+                .filter(|&(s, e, _)| s < hi && lo < e)
+                .collect();
+            let found: Vec<(usize, usize, usize)> = t.all_overlaps(&lo, &hi).into_iter()
+                .map(|(s, e, v)| (*s, *e, *v))
+                .collect();
+            assert_eq!(expected, found);
+            match t.any_overlap(&lo, &hi) {
+                None => assert!(expected.is_empty()),
+                Some((s, e, _)) => assert!(*s < hi && lo < *e),
+            }
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn empty_interval_panics() {
+        let mut t = IntervalTree::new();
+        t.insert(3, 3, ());
+    }
+
+    #[test]
+    #[should_panic]
+    fn empty_query_panics() {
+        // [5, 5) holds nothing, so it can't overlap [3, 8)
+        let mut t = IntervalTree::new();
+        t.insert(3, 8, ());
+        t.any_overlap(&5, &5);
+    }
+
+    #[test]
+    #[should_panic]
+    fn inverted_query_panics() {
+        let mut t = IntervalTree::new();
+        t.insert(3, 8, ());
+        t.all_overlaps(&6, &4);
+    }
+}
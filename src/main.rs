@@ -1,234 +1,937 @@
 // binary tree
-#![feature(box_patterns)]
 
-use std::mem;
+#[cfg(feature = "serde")]
+extern crate serde;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+
 mod util;
+mod red_black;
+mod persistent;
+mod tree_set;
+mod interval_tree;
+mod codec;
+mod union_find;
+mod percolation;
+mod sort;
+mod graph;
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::iter::FromIterator;
+use std::mem;
+use std::ops::Index;
+use std::thread;
+
+use graph::Graph;
+use interval_tree::IntervalTree;
+use persistent::PersistentTree;
+use red_black::RedBlackTree;
+use tree_set::TreeSet;
+use union_find::{KeyedUnionFind, UnionFind};
+use union_find::concurrent::ConcurrentUnionFind;
+use union_find::pc_quick_union::PotentialUnionFind;
+
+/* Every walk down the tree below is a loop over the links rather
+ * than a recursive call: the tree is not balanced, so sorted inserts
+ * make it as deep as it is large and recursion would overflow the
+ * stack long before memory runs out.
+ */
 
-struct Node {
-    key: usize,
-    val: u32,
+struct Node<K: Ord, V> {
+    key: K,
+    val: V,
     n: u32,
-    left: BinaryTree,
-    right: BinaryTree,
+    left: BinaryTree<K, V>,
+    right: BinaryTree<K, V>,
 }
 
-impl Node {
-    fn new(key: usize, val: u32) -> Self {
+impl<K: Ord, V> Node<K, V> {
+    fn new(key: K, val: V) -> Self {
         Node {
-            key: key,
-            val: val,
+            key,
+            val,
             n: 1,
             left: BinaryTree::new(),
             right: BinaryTree::new(),
         }
     }
-    fn put(&mut self, key: usize, val: u32) {
-        if self.key == key {
-            self.val = val;
-        } else if key < self.key {
-            self.left.put(key, val);
-        } else {
-            self.right.put(key, val);
+    fn count(&self) -> u32 {
+        self.n
+    }
+}
+
+struct BinaryTree<K: Ord, V> {
+    root: Option<Box<Node<K, V>>>
+}
+
+impl<K: Ord, V> BinaryTree<K, V> {
+    fn new() -> Self {
+        BinaryTree { root: None }
+    }
+    fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+    fn put(&mut self, key: K, val: V) {
+        self.insert(key, val);
+    }
+    /// Like put, but hands back the value it replaced
+    fn insert(&mut self, key: K, val: V) -> Option<V> {
+        match self.entry(key) {
+            Entry::Occupied(mut entry) => Some(mem::replace(entry.get_mut(), val)),
+            Entry::Vacant(entry) => {
+                entry.insert(val);
+                None
+            },
         }
-        self.n = 1 + self.left.count() + self.right.count();
     }
-    fn get(&self, key: usize) -> Option<u32> {
-        if self.key == key {
-            Some(self.val)
-        } else if key < self.key {
-            self.left.get(key)
+    /// Finds the entry for key, to update or fill it in place
+    fn entry(&mut self, key: K) -> Entry<'_, K, V> {
+        if self.contains_key(&key) {
+            Entry::Occupied(OccupiedEntry { tree: self.walk_to(&key, |_| ()) })
         } else {
-            self.right.get(key)
+            Entry::Vacant(VacantEntry { tree: self, key })
         }
     }
-    fn min_key(&self) -> Option<usize> {
-        match self.left.min_key() {
-            None => Some(self.key),
-            Some(key) => Some(key),
+    /// Returns the subtree rooted at key, or the empty one where key
+    /// would go, calling resize on the count of every node passed
+    fn walk_to<F: FnMut(&mut u32)>(&mut self, key: &K, mut resize: F) -> &mut BinaryTree<K, V> {
+        let mut tree = self;
+        while tree.root.as_ref().is_some_and(|node| node.key != *key) {
+            let node = tree.root.as_mut().expect("checked by the loop");
+            resize(&mut node.n);
+            tree = if *key < node.key { &mut node.left } else { &mut node.right };
         }
+        tree
     }
-    fn max_key(&self) -> Option<usize> {
-        match self.right.max_key() {
-            None => Some(self.key),
-            Some(key) => Some(key),
+    fn contains_key(&self, key: &K) -> bool {
+        self.get(key).is_some()
+    }
+    // Maybe monad much?
+    fn get(&self, key: &K) -> Option<&V> {
+        let mut tree = self;
+        while let Some(ref node) = tree.root {
+            if node.key == *key {
+                return Some(&node.val);
+            }
+            tree = if *key < node.key { &node.left } else { &node.right };
         }
+        None
     }
-    fn floor(&self, key: usize) -> Option<usize> {
-        if self.key == key {
-            Some(key)
-        } else if key < self.key {
-            self.left.floor(key)
-        } else {
-            match self.right.floor(key) {
-                None => Some(self.key),
-                Some(sub_tree_floor) => Some(sub_tree_floor),
+    fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let mut tree = self;
+        while let Some(ref mut node) = tree.root {
+            if node.key == *key {
+                return Some(&mut node.val);
             }
+            tree = if *key < node.key { &mut node.left } else { &mut node.right };
         }
+        None
     }
-    fn count(&self) -> u32 {
-        self.n
+    fn min_key(&self) -> Option<&K> {
+        let mut out = None;
+        let mut tree = self;
+        while let Some(ref node) = tree.root {
+            out = Some(&node.key);
+            tree = &node.left;
+        }
+        out
     }
-    fn iter_rec(&self, v: &mut Vec<usize>) {
-        self.left.iter_rec(v);
-        self.right.iter_rec(v);
-        v.push(self.key);
-    }
-    /// Finds first node to the left with empty left
-    fn pull_left(self) -> Node {
-        match self.left.root {
-            None => { self },
-            Some(box_left_node) => box_left_node.pull_left(),
-        }
-    }
-    /// If the node itself should be completely deleted, it returns true
-    /// for the parent to do it.
-    fn delete(&mut self, key: usize) -> bool {
-        let mut out = false;
-        if self.key == key {
-            // we assume we shouldn't be deleted, and that we can pull
-            // up one of our nodes
-            match mem::replace(&mut self.left.root, None) {
-                None => match mem::replace(&mut self.right.root, None) {
-                    // we are the node to delete and there are no
-                    // nodes below to pull up communicate up that we
-                    // should be deleted
-                    None => {
-                        out = true;
-                    }
-                    Some(box_right_node) => {
-                        // pull up right node since it is alone
-                        *self = *box_right_node;
-                    },
-                },
-                Some(box_left_node) => {
-                    match mem::replace(&mut self.right.root, None) {
-                        None => {
-                            // pull up left node, since it is alone
-                            *self = *box_left_node;
-                        },
-                        Some(right_node) => {
-                            // both nodes are present
-                            *self = Node {
-                                left: BinaryTree { root: Some(box_left_node) },
-                                .. right_node.pull_left()
-                            };
-                        },
-                    }
-                },
+    fn max_key(&self) -> Option<&K> {
+        let mut out = None;
+        let mut tree = self;
+        while let Some(ref node) = tree.root {
+            out = Some(&node.key);
+            tree = &node.right;
+        }
+        out
+    }
+    fn floor(&self, key: &K) -> Option<&K> {
+        // Every time we go right, the node we leave behind is the
+        // best candidate so far
+        let mut out = None;
+        let mut tree = self;
+        while let Some(ref node) = tree.root {
+            if node.key == *key {
+                return Some(&node.key);
+            } else if *key < node.key {
+                tree = &node.left;
+            } else {
+                out = Some(&node.key);
+                tree = &node.right;
             }
-        } else if key < self.key {
-            self.left.delete(key);
-        } else {
-            self.right.delete(key);
         }
-        self.n = 1 + self.right.count() + self.left.count();
         out
     }
-}
-
-struct BinaryTree {
-    root: Option<Box<Node>>
-}
-
-impl BinaryTree {
-    fn new() -> Self {
-        BinaryTree { root: None }
+    fn ceiling(&self, key: &K) -> Option<&K> {
+        let mut out = None;
+        let mut tree = self;
+        while let Some(ref node) = tree.root {
+            if node.key == *key {
+                return Some(&node.key);
+            } else if *key > node.key {
+                tree = &node.right;
+            } else {
+                out = Some(&node.key);
+                tree = &node.left;
+            }
+        }
+        out
     }
-    fn is_empty(&self) -> bool {
+    fn count(&self) -> u32 {
         match self.root {
-            None => true,
-            _ => false,
+            None => 0,
+            Some(ref node) => node.count(),
+        }
+    }
+    /// Number of keys strictly smaller than key
+    fn rank(&self, key: &K) -> u32 {
+        // Everything to the left is smaller, and so is everything we
+        // skip when going right
+        let mut out = 0;
+        let mut tree = self;
+        while let Some(ref node) = tree.root {
+            if node.key == *key {
+                return out + node.left.count();
+            } else if *key < node.key {
+                tree = &node.left;
+            } else {
+                out += 1 + node.left.count();
+                tree = &node.right;
+            }
+        }
+        out
+    }
+    /// Key with rank k, i.e. the (k+1)-th smallest key
+    fn select(&self, mut k: u32) -> Option<&K> {
+        let mut tree = self;
+        while let Some(ref node) = tree.root {
+            let left_count = node.left.count();
+            if k < left_count {
+                tree = &node.left;
+            } else if k > left_count {
+                k -= left_count + 1;
+                tree = &node.right;
+            } else {
+                return Some(&node.key);
+            }
+        }
+        None
+    }
+    /// Number of keys in [lo, hi)
+    fn size_between(&self, lo: &K, hi: &K) -> u32 {
+        if hi <= lo {
+            0
+        } else {
+            self.rank(hi) - self.rank(lo)
+        }
+    }
+    /// Sorted keys in [lo, hi)
+    fn keys_in_range(&self, lo: &K, hi: &K) -> Vec<&K> {
+        self.range(lo, hi).map(|(k, _)| k).collect()
+    }
+    /// Sorted entries in [lo, hi), found as they are consumed
+    fn range(&self, lo: &K, hi: &K) -> Range<'_, K, V> {
+        // The walk never descends into subtrees below lo, and knowing
+        // how many keys are in range it can stop without holding hi
+        let mut range = Range {
+            stack: Vec::new(),
+            remaining: self.size_between(lo, hi) as usize,
+        };
+        let mut tree = self;
+        while let Some(ref node) = tree.root {
+            if *lo <= node.key {
+                range.stack.push(node);
+                tree = &node.left;
+            } else {
+                tree = &node.right;
+            }
+        }
+        range
+    }
+    /// Sorted (in-order) traversal, reversible with .rev()
+    fn iter(&self) -> Iter<'_, K, V> {
+        let mut it = Iter {
+            front: Vec::new(),
+            back: Vec::new(),
+            remaining: self.count() as usize,
+        };
+        it.push_left(self);
+        it.push_right(self);
+        it
+    }
+    /// Each node before its children, left subtree first
+    fn pre_order(&self) -> PreOrder<'_, K, V> {
+        let mut stack = Vec::new();
+        if let Some(ref node) = self.root {
+            stack.push(&**node);
+        }
+        PreOrder { stack }
+    }
+    /// Each node after both of its subtrees
+    fn post_order(&self) -> PostOrder<'_, K, V> {
+        let mut stack = Vec::new();
+        if let Some(ref node) = self.root {
+            stack.push((&**node, false));
         }
+        PostOrder { stack }
     }
-    fn put(&mut self, key: usize, val: u32) {
-        match mem::replace(&mut self.root, None) {
-            None => {
-                self.root = Some(Box::new(Node::new(key, val)));
+    /// Breadth-first, level by level from the root
+    fn level_order(&self) -> LevelOrder<'_, K, V> {
+        let mut queue = VecDeque::new();
+        if let Some(ref node) = self.root {
+            queue.push_back(&**node);
+        }
+        LevelOrder { queue }
+    }
+    /// Detaches the smallest node, fixing the counts on its path
+    fn take_min(&mut self) -> Option<Box<Node<K, V>>> {
+        let mut tree = self;
+        while tree.root.as_ref().is_some_and(|node| !node.left.is_empty()) {
+            let node = tree.root.as_mut().expect("checked by the loop");
+            node.n -= 1;
+            tree = &mut node.left;
+        }
+        let mut min = tree.root.take()?;
+        // the minimum has no left child, its right one takes its place
+        tree.root = min.right.root.take();
+        min.n = 1;
+        Some(min)
+    }
+    /// Detaches the largest node, fixing the counts on its path
+    fn take_max(&mut self) -> Option<Box<Node<K, V>>> {
+        let mut tree = self;
+        while tree.root.as_ref().is_some_and(|node| !node.right.is_empty()) {
+            let node = tree.root.as_mut().expect("checked by the loop");
+            node.n -= 1;
+            tree = &mut node.right;
+        }
+        let mut max = tree.root.take()?;
+        tree.root = max.left.root.take();
+        max.n = 1;
+        Some(max)
+    }
+    /// Unlinks the root node, pulling up one of its children or its
+    /// successor (the minimum of the right subtree) in its place
+    fn take_root(&mut self) -> Option<Box<Node<K, V>>> {
+        let mut node = self.root.take()?;
+        self.root = match (node.left.root.take(), node.right.root.take()) {
+            (None, None) => None,
+            (Some(left), None) => Some(left),
+            (None, Some(right)) => Some(right),
+            (Some(left), Some(right)) => {
+                node.right.root = Some(right);
+                let mut successor = node.right.take_min().expect("right is not empty");
+                successor.left.root = Some(left);
+                successor.right.root = node.right.root.take();
+                successor.n = node.n - 1;
+                Some(successor)
             },
-            Some(box_node) => {
-                let mut node = *box_node;
-                node.put(key,val);
-                self.root = Some(Box::new(node));
+        };
+        node.n = 1;
+        Some(node)
+    }
+    fn delete(&mut self, key: &K) {
+        self.remove(key);
+    }
+    /// Like delete, but hands back the removed value
+    fn remove(&mut self, key: &K) -> Option<V> {
+        if !self.contains_key(key) {
+            return None; // nothing should be deleted
+        }
+        // Every node on the path to the key loses one descendant
+        let tree = self.walk_to(key, |n| *n -= 1);
+        tree.take_root().map(|node| node.val)
+    }
+    /// Builds a perfectly balanced tree in O(n) from entries sorted by
+    /// strictly increasing key
+    fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let entries: Vec<(K, V)> = iter.into_iter().collect();
+        assert!(entries.windows(2).all(|w| w[0].0 < w[1].0), "keys are not sorted");
+        let n = entries.len() as u32;
+        BinaryTree::build_sorted(&mut entries.into_iter(), n)
+    }
+    // Takes the next n entries; the middle one becomes the root. The
+    // recursion is only lg(n) deep since the halves are balanced.
+    fn build_sorted<I: Iterator<Item = (K, V)>>(entries: &mut I, n: u32) -> Self {
+        let mut tree = BinaryTree::new();
+        if n > 0 {
+            let left = BinaryTree::build_sorted(entries, n / 2);
+            let (key, val) = entries.next().expect("fewer entries than counted");
+            let mut node = Node::new(key, val);
+            node.left = left;
+            node.right = BinaryTree::build_sorted(entries, n - n / 2 - 1);
+            node.n = n;
+            tree.root = Some(Box::new(node));
+        }
+        tree
+    }
+    /// Moves every key >= key into the returned tree
+    fn split_off(&mut self, key: &K) -> BinaryTree<K, V> {
+        let mut more = BinaryTree::new();
+        // Walk down the search path, hanging each node (with the
+        // subtree on its far side) from the open end of one of the
+        // halves: the right spine of self or the left spine of more.
+        let mut cur = self.root.take();
+        {
+            let mut less_end = &mut *self;
+            let mut more_end = &mut more;
+            while let Some(mut node) = cur {
+                if node.key < *key {
+                    cur = node.right.root.take();
+                    less_end.root = Some(node);
+                    less_end = &mut less_end.root.as_mut().expect("just set").right;
+                } else {
+                    cur = node.left.root.take();
+                    more_end.root = Some(node);
+                    more_end = &mut more_end.root.as_mut().expect("just set").left;
+                }
             }
         }
+        // Only the nodes on those spines changed size
+        self.fix_spine_counts(true);
+        more.fix_spine_counts(false);
+        more
     }
-    // Maybe monad much?
-    fn get(&self, key: usize) -> Option<u32> {
-        match self.root {
-            None => None,
-            Some(ref box_node) => {
-                let ref node = *box_node;
-                node.get(key)
+    /// Recomputes n for the nodes on the right (or left) spine, bottom up
+    fn fix_spine_counts(&mut self, right: bool) {
+        let mut sizes = Vec::new();
+        let mut tree = &*self;
+        while let Some(ref node) = tree.root {
+            if right {
+                sizes.push(1 + node.left.count());
+                tree = &node.right;
+            } else {
+                sizes.push(1 + node.right.count());
+                tree = &node.left;
+            }
+        }
+        // each node holds its own size plus everything below on the spine
+        for i in (1..sizes.len()).rev() {
+            sizes[i - 1] += sizes[i];
+        }
+        let mut tree = self;
+        for n in sizes {
+            let node = tree.root.as_mut().expect("same spine as above");
+            node.n = n;
+            tree = if right { &mut node.right } else { &mut node.left };
+        }
+    }
+    /// Moves all of other into self; all the keys of one tree must be
+    /// smaller than all the keys of the other
+    fn join(&mut self, mut other: BinaryTree<K, V>) {
+        if other.is_empty() {
+            return;
+        }
+        if self.is_empty() || self.min_key() > other.max_key() {
+            mem::swap(self, &mut other);
+        }
+        if other.is_empty() {
+            return;
+        }
+        assert!(self.max_key() < other.min_key(), "joined trees overlap");
+        // The smallest key of the larger tree goes between both
+        let mut middle = other.take_min().expect("other is not empty");
+        middle.left.root = self.root.take();
+        middle.right.root = other.root.take();
+        middle.n = 1 + middle.left.count() + middle.right.count();
+        self.root = Some(middle);
+    }
+    fn delete_min(&mut self) -> Option<(K, V)> {
+        self.take_min().map(|node| {
+            let node = *node;
+            (node.key, node.val)
+        })
+    }
+    fn delete_max(&mut self) -> Option<(K, V)> {
+        self.take_max().map(|node| {
+            let node = *node;
+            (node.key, node.val)
+        })
+    }
+}
+
+impl<K: Ord, V> Drop for BinaryTree<K, V> {
+    fn drop(&mut self) {
+        // The derived drop would recurse once per level
+        let mut stack = Vec::new();
+        stack.extend(self.root.take());
+        while let Some(mut node) = stack.pop() {
+            stack.extend(node.left.root.take());
+            stack.extend(node.right.root.take());
+        }
+    }
+}
+
+/// A broken BinaryTree invariant, naming the offending key
+#[cfg(any(debug_assertions, test))]
+#[derive(Debug, PartialEq)]
+pub enum InvariantError<'a, K: 'a> {
+    OutOfOrder(&'a K), // smaller than the key before it in order
+    Duplicate(&'a K),
+    WrongCount { key: &'a K, n: u32, actual: u32 },
+}
+
+#[cfg(any(debug_assertions, test))]
+impl<'a, K: fmt::Debug> fmt::Display for InvariantError<'a, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InvariantError::OutOfOrder(key) => write!(f, "key {:?} is out of order", key),
+            InvariantError::Duplicate(key) => write!(f, "key {:?} appears twice", key),
+            InvariantError::WrongCount { key, n, actual } => {
+                write!(f, "node {:?} has n = {} but its subtree has {} nodes", key, n, actual)
             },
         }
     }
-    fn min_key(&self) -> Option<usize> {
-        match self.root {
-            None => None,
-            Some(ref box_node) => {
-                let ref node = *box_node;
-                node.min_key()
+}
+
+#[cfg(any(debug_assertions, test))]
+impl<K: Ord, V> BinaryTree<K, V> {
+    /// Checks in O(n) that every n is 1 + left.count() + right.count()
+    /// and that the keys are in symmetric order with no repeats. The
+    /// nodes have no parent links, so there is nothing else to agree.
+    pub fn check_invariants(&self) -> Result<(), InvariantError<'_, K>> {
+        // Counts first, since iter() trusts them. Post-order: when a
+        // node comes back expanded, the real sizes of its subtrees
+        // are on top of sizes (right above left).
+        let mut stack = Vec::new();
+        let mut sizes: Vec<u32> = Vec::new();
+        if let Some(ref node) = self.root {
+            stack.push((&**node, false));
+        }
+        while let Some((node, expanded)) = stack.pop() {
+            if expanded {
+                let right = if node.right.is_empty() { 0 } else { sizes.pop().unwrap_or(0) };
+                let left = if node.left.is_empty() { 0 } else { sizes.pop().unwrap_or(0) };
+                let actual = 1 + left + right;
+                if node.n != actual {
+                    return Err(InvariantError::WrongCount { key: &node.key, n: node.n, actual });
+                }
+                sizes.push(actual);
+                continue;
+            }
+            stack.push((node, true));
+            if let Some(ref right) = node.right.root {
+                stack.push((right, false));
+            }
+            if let Some(ref left) = node.left.root {
+                stack.push((left, false));
+            }
+        }
+        let mut prev: Option<&K> = None;
+        for (key, _) in self.iter() {
+            if let Some(prev) = prev {
+                if *prev == *key {
+                    return Err(InvariantError::Duplicate(key));
+                } else if *prev > *key {
+                    return Err(InvariantError::OutOfOrder(key));
+                }
             }
+            prev = Some(key);
         }
+        Ok(())
     }
-    fn max_key(&self) -> Option<usize> {
-        match self.root {
-            None => None,
-            Some(ref box_node) => {
-                let ref node = *box_node;
-                node.max_key()
+}
+
+#[cfg(test)]
+impl<K: Ord, V> BinaryTree<K, V> {
+    /// Number of nodes on the longest root to leaf path
+    fn height(&self) -> u32 {
+        let mut height = 0;
+        let mut stack = vec![(self, 0)];
+        while let Some((tree, depth)) = stack.pop() {
+            height = height.max(depth);
+            if let Some(ref node) = tree.root {
+                stack.push((&node.left, depth + 1));
+                stack.push((&node.right, depth + 1));
             }
         }
+        height
     }
-    fn floor(&self, key: usize) -> Option<usize> {
-        match self.root {
-            None => None,
-            Some(ref box_node) => {
-                let ref node = *box_node;
-                node.floor(key)
+}
+
+// Verifies a tree after it changes, in debug builds only
+macro_rules! check {
+    ($tree:expr) => {
+        #[cfg(any(debug_assertions, test))]
+        {
+            if let Err(e) = $tree.check_invariants() {
+                panic!("broken tree: {}", e);
             }
         }
+    };
+}
+
+impl<K: Ord + fmt::Debug, V: fmt::Debug> fmt::Debug for Node<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Only this node: printing the children would recurse
+        f.debug_struct("Node")
+            .field("key", &self.key)
+            .field("val", &self.val)
+            .field("n", &self.n)
+            .finish()
     }
-    fn count(&self) -> u32 {
-        match self.root {
-            None => 0,
-            Some(ref box_node) => {
-                let ref node = *box_node;
-                node.count()
+}
+
+impl<K: Ord + fmt::Debug, V: fmt::Debug> fmt::Debug for BinaryTree<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Side {
+    Root,
+    Right,
+    Left,
+}
+
+/// Draws the tree sideways, root on the left and larger keys on top:
+///
+/// ```text
+///     /-- 3: c
+/// --- 2: b
+///     \-- 1: a
+/// ```
+impl<K: Ord + fmt::Display, V: fmt::Display> fmt::Display for BinaryTree<K, V> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Reverse in-order walk; the flag marks nodes whose subtrees
+        // were already pushed, so the node itself is drawn next
+        let mut stack = Vec::new();
+        if let Some(ref node) = self.root {
+            stack.push((&**node, String::new(), Side::Root, false));
+        }
+        while let Some((node, prefix, side, expanded)) = stack.pop() {
+            if expanded {
+                let branch = match side {
+                    Side::Root => "--- ",
+                    Side::Right => "/-- ",
+                    Side::Left => "\\-- ",
+                };
+                writeln!(f, "{}{}{}: {}", prefix, branch, node.key, node.val)?;
+                continue;
+            }
+            // A child on the side facing our parent needs a bar to
+            // connect the parent with the rest of our subtree
+            if let Some(ref left) = node.left.root {
+                let bar = if side == Side::Right { "|   " } else { "    " };
+                stack.push((&**left, prefix.clone() + bar, Side::Left, false));
+            }
+            let right_bar = if side == Side::Left { "|   " } else { "    " };
+            let right_prefix = prefix.clone() + right_bar;
+            stack.push((node, prefix, side, true));
+            if let Some(ref right) = node.right.root {
+                stack.push((&**right, right_prefix, Side::Right, false));
             }
         }
+        Ok(())
     }
-    fn iter_rec(&self, v: &mut Vec<usize>) {
+}
+
+impl<K: Ord + fmt::Display, V: fmt::Display> BinaryTree<K, V> {
+    /// Graphviz DOT description of the tree, labelling each node with
+    /// its key, value and subtree size. Render with `dot -Tpng`.
+    fn to_dot(&self) -> String {
+        fn escape(s: String) -> String {
+            s.replace('\\', "\\\\").replace('"', "\\\"")
+        }
+        let mut out = String::from("digraph BinaryTree {\n    node [shape=box];\n");
+        let mut next_id = 0;
+        let mut stack = Vec::new();
         if let Some(ref node) = self.root {
-            node.iter_rec(v);
+            stack.push((&**node, next_id));
+            next_id += 1;
+        }
+        while let Some((node, id)) = stack.pop() {
+            out += &format!("    n{} [label=\"{}: {}\\nn = {}\"];\n", id,
+                            escape(node.key.to_string()), escape(node.val.to_string()), node.n);
+            // With a single child, a dot stands in for the missing one
+            // so that dot still draws left children on the left
+            let only_child = node.left.is_empty() != node.right.is_empty();
+            for child in [&node.left, &node.right].iter() {
+                let child_id = next_id;
+                next_id += 1;
+                match child.root {
+                    Some(ref child) => stack.push((&**child, child_id)),
+                    None if only_child => {
+                        out += &format!("    n{} [shape=point];\n", child_id);
+                    },
+                    None => continue,
+                }
+                out += &format!("    n{} -> n{};\n", id, child_id);
+            }
+        }
+        out += "}\n";
+        out
+    }
+}
+
+enum Entry<'a, K: Ord + 'a, V: 'a> {
+    Occupied(OccupiedEntry<'a, K, V>),
+    Vacant(VacantEntry<'a, K, V>),
+}
+
+struct OccupiedEntry<'a, K: Ord + 'a, V: 'a> {
+    tree: &'a mut BinaryTree<K, V>, // subtree rooted at the entry
+}
+
+struct VacantEntry<'a, K: Ord + 'a, V: 'a> {
+    tree: &'a mut BinaryTree<K, V>, // whole tree, to grow the counts
+    key: K,
+}
+
+impl<'a, K: Ord, V> Entry<'a, K, V> {
+    fn or_insert(self, default: V) -> &'a mut V {
+        self.or_insert_with(|| default)
+    }
+    fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default()),
         }
     }
-    fn iter(&self) -> BinaryTreeIter {
-        let mut v = Vec::with_capacity(self.count() as usize);
-        self.iter_rec(&mut v);
-        BinaryTreeIter { v: v }
+    fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            },
+            vacant => vacant,
+        }
     }
-    fn delete(&mut self, key: usize) {
-        match mem::replace(&mut self.root, None) {
-            None => (), // nothing should be deleted
-            Some(mut box_node) => {
-                if !box_node.delete(key) {
-                    self.root = Some(box_node); // restore what was deleted
-                }
+}
+
+impl<'a, K: Ord, V> OccupiedEntry<'a, K, V> {
+    fn get_mut(&mut self) -> &mut V {
+        &mut self.tree.root.as_mut().expect("occupied").val
+    }
+    fn into_mut(self) -> &'a mut V {
+        &mut self.tree.root.as_mut().expect("occupied").val
+    }
+}
+
+impl<'a, K: Ord, V> VacantEntry<'a, K, V> {
+    fn insert(self, val: V) -> &'a mut V {
+        let tree = self.tree.walk_to(&self.key, |n| *n += 1);
+        tree.root = Some(Box::new(Node::new(self.key, val)));
+        &mut tree.root.as_mut().expect("just inserted").val
+    }
+}
+
+impl<K: Ord, V> FromIterator<(K, V)> for BinaryTree<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut b = BinaryTree::new();
+        b.extend(iter);
+        b
+    }
+}
+
+impl<K: Ord, V> Extend<(K, V)> for BinaryTree<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        for (key, val) in iter {
+            self.put(key, val);
+        }
+    }
+}
+
+impl<K: Ord, V> Index<&K> for BinaryTree<K, V> {
+    type Output = V;
+
+    fn index(&self, key: &K) -> &V {
+        self.get(key).expect("key not in BinaryTree")
+    }
+}
+
+/* The iterators keep an explicit stack of the nodes still to be
+ * visited instead of collecting the whole tree up front, so they
+ * only use O(height) memory and do work as they are consumed.
+ */
+
+struct Iter<'a, K: Ord + 'a, V: 'a> {
+    front: Vec<&'a Node<K, V>>, // left spines, smallest on top
+    back: Vec<&'a Node<K, V>>,  // right spines, largest on top
+    remaining: usize,           // stops both ends once they meet
+}
+
+impl<'a, K: Ord, V> Iter<'a, K, V> {
+    fn push_left(&mut self, mut tree: &'a BinaryTree<K, V>) {
+        while let Some(ref node) = tree.root {
+            self.front.push(node);
+            tree = &node.left;
+        }
+    }
+    fn push_right(&mut self, mut tree: &'a BinaryTree<K, V>) {
+        while let Some(ref node) = tree.root {
+            self.back.push(node);
+            tree = &node.right;
+        }
+    }
+}
+
+impl<'a, K: Ord, V> Iterator for Iter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.front.pop()?;
+        self.push_left(&node.right);
+        self.remaining -= 1;
+        Some((&node.key, &node.val))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+struct Range<'a, K: Ord + 'a, V: 'a> {
+    stack: Vec<&'a Node<K, V>>, // nodes at or past lo, smallest on top
+    remaining: usize,           // keys left before hi
+}
+
+impl<'a, K: Ord, V> Iterator for Range<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.stack.pop()?;
+        let mut tree = &node.right;
+        while let Some(ref child) = tree.root {
+            self.stack.push(child);
+            tree = &child.left;
+        }
+        self.remaining -= 1;
+        Some((&node.key, &node.val))
+    }
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, K: Ord, V> DoubleEndedIterator for Iter<'a, K, V> {
+    fn next_back(&mut self) -> Option<(&'a K, &'a V)> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = self.back.pop()?;
+        self.push_right(&node.left);
+        self.remaining -= 1;
+        Some((&node.key, &node.val))
+    }
+}
+
+impl<'a, K: Ord, V> ExactSizeIterator for Iter<'a, K, V> {}
+
+struct PreOrder<'a, K: Ord + 'a, V: 'a> {
+    stack: Vec<&'a Node<K, V>>,
+}
+
+impl<'a, K: Ord, V> Iterator for PreOrder<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        let node = self.stack.pop()?;
+        // right goes in first so that left comes out first
+        if let Some(ref right) = node.right.root {
+            self.stack.push(right);
+        }
+        if let Some(ref left) = node.left.root {
+            self.stack.push(left);
+        }
+        Some((&node.key, &node.val))
+    }
+}
+
+struct PostOrder<'a, K: Ord + 'a, V: 'a> {
+    // The flag marks nodes whose children were already pushed
+    stack: Vec<(&'a Node<K, V>, bool)>,
+}
+
+impl<'a, K: Ord, V> Iterator for PostOrder<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        loop {
+            let (node, expanded) = self.stack.pop()?;
+            if expanded {
+                return Some((&node.key, &node.val));
+            }
+            self.stack.push((node, true));
+            if let Some(ref right) = node.right.root {
+                self.stack.push((right, false));
             }
+            if let Some(ref left) = node.left.root {
+                self.stack.push((left, false));
+            }
+        }
+    }
+}
+
+struct LevelOrder<'a, K: Ord + 'a, V: 'a> {
+    queue: VecDeque<&'a Node<K, V>>,
+}
+
+impl<'a, K: Ord, V> Iterator for LevelOrder<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<(&'a K, &'a V)> {
+        let node = self.queue.pop_front()?;
+        if let Some(ref left) = node.left.root {
+            self.queue.push_back(left);
+        }
+        if let Some(ref right) = node.right.root {
+            self.queue.push_back(right);
+        }
+        Some((&node.key, &node.val))
+    }
+}
+
+/// Consumes the tree, yielding the entries in sorted order
+struct IntoIter<K: Ord, V> {
+    stack: Vec<Box<Node<K, V>>>,
+}
+
+impl<K: Ord, V> IntoIter<K, V> {
+    fn push_left(&mut self, mut link: Option<Box<Node<K, V>>>) {
+        while let Some(mut node) = link {
+            link = node.left.root.take();
+            self.stack.push(node);
         }
     }
 }
 
-struct BinaryTreeIter {
-    v: Vec<usize>,
+impl<K: Ord, V> Iterator for IntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<(K, V)> {
+        let mut node = self.stack.pop()?;
+        let right = node.right.root.take();
+        self.push_left(right);
+        let node = *node;
+        Some((node.key, node.val))
+    }
 }
 
-impl Iterator for BinaryTreeIter {
-    type Item = usize;
+impl<K: Ord, V> IntoIterator for BinaryTree<K, V> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V>;
 
-    fn next(&mut self) -> Option<usize> {
-        self.v.pop()
+    fn into_iter(mut self) -> IntoIter<K, V> {
+        let mut it = IntoIter { stack: Vec::new() };
+        it.push_left(self.root.take());
+        it
+    }
+}
+
+impl<'a, K: Ord, V> IntoIterator for &'a BinaryTree<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Iter<'a, K, V> {
+        self.iter()
     }
 }
 
@@ -237,28 +940,610 @@ fn main() {
     assert!(b.is_empty());
     for i in 0..10 {
         b.put(i, i as u32);
-        assert_eq!(Some(0), b.min_key());
+        check!(b);
+        assert_eq!(Some(&0), b.min_key());
         assert!(!b.is_empty());
-        assert_eq!(Some(i), b.max_key());
-        assert_eq!(Some(i), b.floor(i));
-        assert_eq!(Some(i), b.floor(10));
+        assert_eq!(Some(&i), b.max_key());
+        assert_eq!(Some(&i), b.floor(&i));
+        assert_eq!(Some(&i), b.floor(&10));
         assert_eq!(i as u32, b.count() - 1);
     }
-    for i in b.iter() {
-        assert_eq!(Some(i as u32), b.get(i));
+    for (i, x) in &b {
+        assert_eq!(*i as u32, *x);
     }
+    // sorted inserts make a chain leaning right
+    println!("After sorted inserts:\n{}", b);
+    let saved = b.to_bytes().unwrap();
+    let loaded: BinaryTree<usize, u32> = BinaryTree::from_bytes(&saved).unwrap();
+    check!(loaded);
+    assert!(b.iter().eq(loaded.iter()));
+    println!("The chain loads back balanced:\n{}", loaded);
     let v = util::rand_vec(10);
+    for (i, x) in v.iter().enumerate() {
+        b.put(i, *x as u32);
+        check!(b);
+        assert_eq!(Some(&(*x as u32)), b.get(&i));
+    }
+    if let Some(val) = b.get_mut(&3) {
+        *val += 1;
+    }
+    assert_eq!(Some(&(v[3] as u32 + 1)), b.get(&3));
+    // every traversal visits each node exactly once
+    let n = b.count() as usize;
+    assert_eq!(n, b.pre_order().count());
+    assert_eq!(n, b.post_order().count());
+    assert_eq!(n, b.level_order().count());
+    assert_eq!(b.pre_order().next(), b.level_order().next());
     for i in 0..10 {
-        b.put(i, v[i] as u32);
-        assert_eq!(Some(v[i] as u32), b.get(i));
+        assert_eq!(i as u32, b.rank(&i));
+        assert_eq!(Some(&i), b.select(i as u32));
+        assert_eq!(Some(&i), b.ceiling(&i));
     }
+    assert_eq!(None, b.ceiling(&10));
+    assert_eq!(None, b.select(10));
+    assert_eq!(3, b.size_between(&2, &5));
+    assert_eq!(vec![&2, &3, &4], b.keys_in_range(&2, &5));
+    assert_eq!(Some((0, v[0] as u32)), b.delete_min());
+    check!(b);
+    assert_eq!(Some((9, v[9] as u32)), b.delete_max());
+    check!(b);
+    assert_eq!(Some(&1), b.min_key());
+    assert_eq!(Some(&8), b.max_key());
     let c = b.count();
-    b.delete(4);
-    assert_eq!(None, b.get(4));
+    b.delete(&4);
+    check!(b);
+    assert_eq!(None, b.get(&4));
     assert_eq!(c - 1, b.count());
-    for i in b.iter() {
-        b.delete(i);
-        assert_eq!(None, b.get(i));
+    let keys: Vec<usize> = b.iter().map(|(k, _)| *k).collect();
+    assert!(util::is_sorted(&keys));
+    for i in keys {
+        b.delete(&i);
+        check!(b);
+        assert_eq!(None, b.get(&i));
     }
     assert_eq!(0, b.count());
+
+    // keys and values need not be Copy
+    let mut words = BinaryTree::new();
+    for w in "the quick brown fox jumps over the lazy dog".split_whitespace() {
+        words.put(w.to_string(), vec![w.len()]);
+        check!(words);
+    }
+    assert_eq!(8, words.count());
+    assert_eq!(Some(&"brown".to_string()), words.min_key());
+    assert_eq!(Some(&"the".to_string()), words.max_key());
+    assert_eq!(Some(&"over".to_string()), words.floor(&"pig".to_string()));
+    if let Some(lens) = words.get_mut(&"fox".to_string()) {
+        lens.push(0);
+    }
+    assert_eq!(Some(&vec![3, 0]), words.get(&"fox".to_string()));
+
+    // sorted data loads balanced, and splits and joins back by key
+    let mut sorted = BinaryTree::from_sorted_iter((0..15).map(|i| (i, i * i)));
+    check!(sorted);
+    assert_eq!(Some(&7), sorted.root.as_ref().map(|node| &node.key));
+    println!("Built from sorted data:\n{}", sorted);
+    println!("{}", sorted.to_dot());
+    let high = sorted.split_off(&10);
+    check!(sorted);
+    check!(high);
+    assert_eq!((10, 5), (sorted.count(), high.count()));
+    assert_eq!(Some(&10), high.min_key());
+    sorted.join(high);
+    check!(sorted);
+    assert_eq!(15, sorted.count());
+    assert_eq!(Some(&196), sorted.get(&14));
+
+    // counting with the entry API, no get-then-put needed
+    let text = "the cat and the hat and the bat";
+    let mut counts = BinaryTree::new();
+    for w in text.split_whitespace() {
+        *counts.entry(w).or_insert(0) += 1;
+        check!(counts);
+    }
+    assert_eq!(3, counts[&"the"]);
+    assert_eq!(2, counts[&"and"]);
+    let mut lengths: BinaryTree<&str, Vec<usize>> = text.split_whitespace()
+        .map(|w| (w, Vec::new()))
+        .collect();
+    for w in text.split_whitespace() {
+        lengths.entry(w).and_modify(|v| v.push(w.len())).or_insert_with(Vec::new);
+        check!(lengths);
+    }
+    assert_eq!(vec![3, 3, 3], lengths[&"the"]);
+    assert_eq!(Some(3), counts.insert("the", 0));
+    assert_eq!(Some(0), counts.remove(&"the"));
+    check!(counts);
+    assert!(!counts.contains_key(&"the"));
+    assert_eq!(None, counts.remove(&"the"));
+
+    // sorted inserts degrade BinaryTree to a chain, but not RedBlackTree
+    let mut rb = RedBlackTree::new();
+    for i in 0..1000 {
+        rb.put(i, i as u32);
+    }
+    assert_eq!(1000, rb.count());
+    assert!(rb.height() <= 20);
+
+    // snapshots of a persistent tree share all but the changed path
+    let v0 = PersistentTree::new().put(1, "one").put(2, "two");
+    let v1 = v0.put(3, "three").delete(&1);
+    assert_eq!(Some(&"one"), v0.get(&1));
+    assert_eq!(None, v1.get(&1));
+    assert_eq!((2, 2), (v0.count(), v1.count()));
+
+    // set algebra merges the sorted traversals
+    let evens: TreeSet<u32> = (0..20).filter(|x| x % 2 == 0).collect();
+    let threes: TreeSet<u32> = (0..20).filter(|x| x % 3 == 0).collect();
+    let sixes: Vec<&u32> = evens.intersection(&threes).collect();
+    assert_eq!(vec![&0, &6, &12, &18], sixes);
+    assert_eq!(13, evens.union(&threes).count());
+
+    // reservations as [start, end) intervals
+    let mut booked = IntervalTree::new();
+    booked.insert(9, 12, "morning");
+    booked.insert(14, 17, "afternoon");
+    assert!(booked.any_overlap(&12, &14).is_none());
+    assert_eq!(2, booked.all_overlaps(&11, &15).len());
+
+    // Union find sized at runtime
+    let mut friends = UnionFind::new(3);
+    let dan = friends.add();
+    assert_eq!(4, friends.len());
+    assert!(!friends.is_empty());
+    friends.union(0, dan);
+    friends.union(1, 2);
+    assert!(friends.connected(dan, 0));
+    assert_eq!(2, friends.count());
+    assert_eq!(2, friends.component_size(2));
+    println!("Friend groups: {:?}", friends.groups());
+    assert_eq!(vec![(2, 2)], friends.size_histogram());
+    // or keyed by anything hashable
+    let mut rivers = KeyedUnionFind::new();
+    rivers.union("Missouri", "Mississippi");
+    rivers.union("Ohio", "Mississippi");
+    rivers.union("Rhine", "Main");
+    assert!(rivers.connected(&"Ohio", &"Missouri"));
+    assert_eq!(2, rivers.count());
+    println!("Rivers that reach the Gulf: {:?}", rivers.component(&"Ohio"));
+    // or tracking how elements relate: with two teams, ann, bob and
+    // cat can't each be on the other team from both of the others
+    let mut teams: PotentialUnionFind<bool> = PotentialUnionFind::new(3);
+    assert!(teams.union(0, 1, true).is_ok());
+    assert!(teams.union(1, 2, true).is_ok());
+    if let Err(e) = teams.union(2, 0, true) {
+        println!("ann and cat: {}", e);
+    }
+    // or shared between threads: each links its own stretch of a ring
+    let ring = ConcurrentUnionFind::new(1000);
+    thread::scope(|scope| {
+        for t in 0..4 {
+            let ring = &ring;
+            scope.spawn(move || {
+                for i in (t * 250)..((t + 1) * 250) {
+                    ring.union(i, (i + 1) % 1000);
+                }
+            });
+        }
+    });
+    assert_eq!(1, ring.count());
+    assert!(ring.connected(0, 500));
+    // Kruskal: the cheapest roads that still connect every town
+    let mut roads = Graph::new(4);
+    roads.add_edge(0, 1, 7);
+    roads.add_edge(1, 2, 3);
+    roads.add_edge(0, 2, 4);
+    roads.add_edge(2, 3, 9);
+    let (kept, cost) = roads.mst();
+    println!("Roads kept: {:?} for {}", kept, cost);
+    assert_eq!(16, cost);
+    assert!(roads.has_cycle());
+    assert_eq!(vec![0, 0, 0, 0], roads.components());
+    percolation::constant::<UnionFind>();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BinaryTree, InvariantError, Node};
+    use std::fmt;
+    use util;
+
+    #[test]
+    fn order_statistics() {
+        const N: usize = 200;
+        let mut b = BinaryTree::new();
+        let mut v = util::rand_vec(N);
+        for x in v.iter() {
+            b.put(*x, ());
+            assert_counts(&b);
+        }
+        v.sort();
+        v.dedup();
+        assert_eq!(v.len() as u32, b.count());
+        for (i, x) in v.iter().enumerate() {
+            assert_eq!(i as u32, b.rank(x));
+            assert_eq!(Some(x), b.select(i as u32));
+        }
+        for x in 0..(N + 1) {
+            let after = v.iter().position(|y| *y >= x).unwrap_or(v.len());
+            assert_eq!(after as u32, b.rank(&x));
+            assert_eq!(v.get(after), b.ceiling(&x));
+        }
+        for _ in 0..50 {
+            let lo = util::rand_upto(N);
+            let hi = util::rand_upto(N);
+            let expected: Vec<&usize> = v.iter().filter(|y| lo <= **y && **y < hi).collect();
+            assert_eq!(expected.len() as u32, b.size_between(&lo, &hi));
+            assert_eq!(expected, b.keys_in_range(&lo, &hi));
+        }
+    }
+
+    fn keys<'a, I: Iterator<Item = (&'a u32, &'a char)>>(it: I) -> Vec<u32> {
+        it.map(|(k, _)| *k).collect()
+    }
+
+    #[test]
+    fn traversals() {
+        //        4
+        //      /   \
+        //     2     6
+        //    / \   / \
+        //   1   3 5   7
+        let mut b = BinaryTree::new();
+        for k in [4, 2, 6, 1, 3, 5, 7].iter() {
+            b.put(*k, (b'a' + *k as u8) as char);
+            assert_counts(&b);
+        }
+        assert_eq!(vec![1, 2, 3, 4, 5, 6, 7], keys(b.iter()));
+        assert_eq!(vec![7, 6, 5, 4, 3, 2, 1], keys(b.iter().rev()));
+        assert_eq!(vec![4, 2, 1, 3, 6, 5, 7], keys(b.pre_order()));
+        assert_eq!(vec![1, 3, 2, 5, 7, 6, 4], keys(b.post_order()));
+        assert_eq!(vec![4, 2, 6, 1, 3, 5, 7], keys(b.level_order()));
+        assert_eq!(7, b.iter().len());
+        // both ends meet in the middle without repeating
+        let mut it = b.iter();
+        assert_eq!(Some((&1, &'b')), it.next());
+        assert_eq!(Some((&7, &'h')), it.next_back());
+        assert_eq!(vec![2, 3, 4, 5, 6], keys(it));
+        let mut n = 0;
+        for (k, v) in &b {
+            assert_eq!(b.get(k), Some(v));
+            n += 1;
+        }
+        assert_eq!(7, n);
+        let owned: Vec<(u32, char)> = b.into_iter().collect();
+        assert_eq!((1, 'b'), owned[0]);
+        assert_eq!((7, 'h'), owned[6]);
+        assert_eq!(7, owned.len());
+
+        let empty: BinaryTree<u32, char> = BinaryTree::new();
+        assert_eq!(None, empty.iter().next());
+        assert_eq!(None, empty.pre_order().next());
+        assert_eq!(None, empty.post_order().next());
+        assert_eq!(None, empty.level_order().next());
+        assert_eq!(0, empty.into_iter().count());
+    }
+
+    // Checks every invariant and returns the count
+    fn assert_counts<K: Ord + fmt::Debug, V>(b: &BinaryTree<K, V>) -> u32 {
+        if let Err(e) = b.check_invariants() {
+            panic!("broken tree: {}", e);
+        }
+        b.count()
+    }
+
+    #[test]
+    fn hibbard_deletion() {
+        //      5
+        //    /   \
+        //   2     8
+        //        / \
+        //       6   9
+        //        \
+        //         7
+        let mut b = BinaryTree::new();
+        for k in [5, 2, 8, 6, 9, 7].iter() {
+            b.put(*k, ());
+            assert_counts(&b);
+        }
+        // 5 has two children; its successor 6 takes its place and
+        // 6's right child 7 moves up, while 8 and 9 stay put
+        b.delete(&5);
+        assert_eq!(5, b.count());
+        assert_counts(&b);
+        let keys: Vec<u32> = b.iter().map(|(k, _)| *k).collect();
+        assert_eq!(vec![2, 6, 7, 8, 9], keys);
+        assert_eq!(Some(&6), b.root.as_ref().map(|node| &node.key));
+        b.delete(&8);
+        assert_counts(&b);
+        assert_eq!(Some((2, ())), b.delete_min());
+        assert_counts(&b);
+        assert_eq!(Some((9, ())), b.delete_max());
+        assert_counts(&b);
+        assert_eq!(Some((7, ())), b.delete_max());
+        assert_counts(&b);
+        assert_eq!(Some((6, ())), b.delete_min());
+        assert_eq!(None, b.delete_min());
+        assert_eq!(None, b.delete_max());
+        assert_eq!(0, assert_counts(&b));
+    }
+
+    #[test]
+    fn matches_btree_map() {
+        use std::collections::BTreeMap;
+        const N: usize = 100;
+        const OPS: usize = 5000;
+        let mut b = BinaryTree::new();
+        let mut m = BTreeMap::new();
+        for i in 0..OPS {
+            let key = util::rand_upto(N);
+            match util::rand_upto(5) {
+                0 | 1 => {
+                    b.put(key, i);
+                    m.insert(key, i);
+                },
+                2 => {
+                    b.delete(&key);
+                    m.remove(&key);
+                },
+                3 => {
+                    let min = m.keys().next().cloned();
+                    if let Some(k) = min {
+                        let val = m.remove(&k).unwrap();
+                        assert_eq!(Some((k, val)), b.delete_min());
+                    } else {
+                        assert_eq!(None, b.delete_min());
+                    }
+                },
+                _ => {
+                    let max = m.keys().next_back().cloned();
+                    if let Some(k) = max {
+                        let val = m.remove(&k).unwrap();
+                        assert_eq!(Some((k, val)), b.delete_max());
+                    } else {
+                        assert_eq!(None, b.delete_max());
+                    }
+                },
+            }
+            assert_eq!(m.len() as u32, assert_counts(&b));
+            assert_eq!(m.get(&key), b.get(&key));
+            assert_eq!(m.keys().next(), b.min_key());
+            assert_eq!(m.keys().next_back(), b.max_key());
+        }
+        let entries: Vec<(&usize, &usize)> = b.iter().collect();
+        let expected: Vec<(&usize, &usize)> = m.iter().collect();
+        assert_eq!(expected, entries);
+    }
+
+    #[test]
+    fn map_api() {
+        let mut b: BinaryTree<u32, String> = (0..10).map(|i| (i, i.to_string())).collect();
+        assert_eq!(10, assert_counts(&b));
+        assert_eq!("7", b[&7]);
+        assert_eq!(None, b.insert(10, "ten".to_string()));
+        assert_counts(&b);
+        assert_eq!(Some("ten".to_string()), b.insert(10, "10".to_string()));
+        assert_eq!(11, assert_counts(&b));
+        b.extend(vec![(11, "11".to_string()), (0, "zero".to_string())]);
+        assert_eq!(12, assert_counts(&b));
+        assert_eq!(Some("zero".to_string()), b.remove(&0));
+        assert_counts(&b);
+        assert_eq!(None, b.remove(&0));
+        assert_eq!(11, assert_counts(&b));
+        // vacant entries grow the counts on the path, occupied don't
+        b.entry(20).or_insert_with(|| "20".to_string()).push('!');
+        assert_eq!(12, assert_counts(&b));
+        b.entry(20).or_insert_with(|| unreachable!()).push('!');
+        assert_counts(&b);
+        assert_eq!("20!!", b[&20]);
+        b.entry(5).and_modify(|v| v.clear()).or_insert_with(String::new);
+        b.entry(6).and_modify(|v| v.clear());
+        b.entry(30).and_modify(|v| v.clear());
+        assert_eq!("", b[&5]);
+        assert_eq!("", b[&6]);
+        assert!(!b.contains_key(&30));
+        assert_eq!(12, assert_counts(&b));
+    }
+
+    #[test]
+    #[should_panic]
+    fn index_missing_key() {
+        let b: BinaryTree<u32, u32> = BinaryTree::new();
+        assert_eq!(0, b[&0]);
+    }
+
+    #[test]
+    fn from_sorted_is_balanced() {
+        for n in 0..100u32 {
+            let b = BinaryTree::from_sorted_iter((0..n).map(|i| (i, i)));
+            assert_eq!(n, assert_counts(&b));
+            // perfectly balanced: height is ceil(lg(n + 1))
+            assert_eq!(32 - n.leading_zeros(), b.height());
+            assert!(b.iter().map(|(k, _)| *k).eq(0..n));
+        }
+    }
+
+    #[test]
+    #[should_panic]
+    fn from_unsorted_panics() {
+        BinaryTree::from_sorted_iter(vec![(2, ()), (1, ())]);
+    }
+
+    #[test]
+    fn split_and_join() {
+        const N: usize = 100;
+        for _ in 0..50 {
+            let v = util::rand_vec(N);
+            let mut b: BinaryTree<usize, usize> = v.iter().map(|x| (*x, *x)).collect();
+            assert_counts(&b);
+            let total = b.count();
+            let at = util::rand_upto(N + 1);
+            let more = b.split_off(&at);
+            assert_eq!(total, assert_counts(&b) + assert_counts(&more));
+            assert!(b.iter().all(|(k, _)| *k < at));
+            assert!(more.iter().all(|(k, _)| *k >= at));
+            // join works with the halves in either order
+            let mut joined = more;
+            joined.join(b);
+            assert_eq!(total, assert_counts(&joined));
+            let mut keys: Vec<usize> = v.clone();
+            keys.sort();
+            keys.dedup();
+            assert!(joined.iter().map(|(k, _)| *k).eq(keys.into_iter()));
+        }
+        let mut empty: BinaryTree<u32, ()> = BinaryTree::new();
+        assert!(empty.split_off(&0).is_empty());
+        empty.join(BinaryTree::new());
+        assert_eq!(0, assert_counts(&empty));
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    #[should_panic]
+    fn join_overlapping_panics() {
+        let mut a = BinaryTree::from_sorted_iter(vec![(1, ()), (5, ())]);
+        let b = BinaryTree::from_sorted_iter(vec![(3, ()), (7, ())]);
+        a.join(b);
+    }
+
+    #[test]
+    fn deep_trees() {
+        use std::thread;
+        // A small stack makes any recursion over the depth fail loudly
+        let handle = thread::Builder::new().stack_size(64 * 1024).spawn(|| {
+            const N: u32 = 10_000;
+            let mut b = BinaryTree::new();
+            for i in 0..N {
+                b.put(i, i);
+            }
+            // once at the end, checking every put would be quadratic
+            assert_eq!(N, assert_counts(&b));
+            assert_eq!(Some(&(N - 1)), b.get(&(N - 1)));
+            assert_eq!(Some(&(N - 1)), b.floor(&(2 * N)));
+            assert_eq!(Some(&0), b.ceiling(&0));
+            assert_eq!(N - 1, b.rank(&(N - 1)));
+            assert_eq!(Some(&(N / 2)), b.select(N / 2));
+            assert_eq!(N as usize, b.iter().count());
+            b.delete(&(N / 2));
+            assert_counts(&b);
+            b.delete(&(N - 1));
+            assert_eq!(N - 2, assert_counts(&b));
+            assert_eq!(None, b.get(&(N / 2)));
+        }).unwrap();
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn million_node_chain() {
+        // Sorted puts would take quadratic time at this size, so link
+        // the same degenerate chain (each key the right child of the
+        // previous one) bottom up.
+        const N: u32 = 1_000_000;
+        let mut b = BinaryTree::new();
+        for i in (0..N).rev() {
+            let mut node = Node::new(i, i);
+            node.n = N - i;
+            node.right.root = b.root.take();
+            b.root = Some(Box::new(node));
+        }
+        assert_eq!(N, assert_counts(&b));
+        assert_eq!(Some(&(N - 1)), b.max_key());
+        assert_eq!(Some(&(N - 2)), b.floor(&(N - 2)));
+        assert_eq!(Some(&(N - 10)), b.select(N - 10));
+        b.put(N, N);
+        assert_counts(&b);
+        assert_eq!(Some(&N), b.get(&N));
+        b.delete(&0);
+        assert_counts(&b);
+        b.delete(&(N - 1));
+        assert_eq!(N - 1, assert_counts(&b));
+        assert_eq!(Some(&1), b.min_key());
+        drop(b);
+    }
+
+    #[test]
+    fn invariant_errors() {
+        //     4
+        //    / \
+        //   2   6
+        let mut b: BinaryTree<u32, ()> = [4, 2, 6].iter().map(|k| (*k, ())).collect();
+        assert_eq!(Ok(()), b.check_invariants());
+        assert_eq!(Ok(()), BinaryTree::<u32, ()>::new().check_invariants());
+        b.root.as_mut().unwrap().n = 4;
+        assert_eq!(Err(InvariantError::WrongCount { key: &4, n: 4, actual: 3 }),
+                   b.check_invariants());
+        let e = b.check_invariants().unwrap_err();
+        assert_eq!("node 4 has n = 4 but its subtree has 3 nodes", e.to_string());
+        b.root.as_mut().unwrap().n = 3;
+        // a stale count below the root is found before the root's
+        b.root.as_mut().unwrap().left.root.as_mut().unwrap().n = 0;
+        assert_eq!(Err(InvariantError::WrongCount { key: &2, n: 0, actual: 1 }),
+                   b.check_invariants());
+        b.root.as_mut().unwrap().left.root.as_mut().unwrap().n = 1;
+        b.root.as_mut().unwrap().left.root.as_mut().unwrap().key = 5;
+        assert_eq!(Err(InvariantError::OutOfOrder(&4)), b.check_invariants());
+        assert_eq!("key 4 is out of order", b.check_invariants().unwrap_err().to_string());
+        b.root.as_mut().unwrap().left.root.as_mut().unwrap().key = 6;
+        assert_eq!(Err(InvariantError::OutOfOrder(&4)), b.check_invariants());
+        b.root.as_mut().unwrap().key = 6;
+        assert_eq!(Err(InvariantError::Duplicate(&6)), b.check_invariants());
+        assert_eq!("key 6 appears twice", b.check_invariants().unwrap_err().to_string());
+        // a missing child must not count as an empty subtree's size
+        let mut c = BinaryTree::new();
+        c.root = Some(Box::new(Node::new(1, ())));
+        c.root.as_mut().unwrap().right.put(2, ());
+        assert_eq!(Err(InvariantError::WrongCount { key: &1, n: 1, actual: 2 }),
+                   c.check_invariants());
+    }
+
+    #[test]
+    fn rendering() {
+        let mut b = BinaryTree::new();
+        for k in [4, 2, 6, 1, 3, 5].iter() {
+            b.put(*k, (b'a' + *k as u8 - 1) as char);
+            assert_counts(&b);
+        }
+        let expected = concat!("    /-- 6: f\n",
+                               "    |   \\-- 5: e\n",
+                               "--- 4: d\n",
+                               "    |   /-- 3: c\n",
+                               "    \\-- 2: b\n",
+                               "        \\-- 1: a\n");
+        assert_eq!(expected, format!("{}", b));
+        assert_eq!("{1: 'a', 2: 'b', 3: 'c', 4: 'd', 5: 'e', 6: 'f'}", format!("{:?}", b));
+        let root = b.root.as_ref().unwrap();
+        assert_eq!("Node { key: 4, val: 'd', n: 6 }", format!("{:?}", root));
+        assert_eq!("", format!("{}", BinaryTree::<u32, u32>::new()));
+
+        let dot = b.to_dot();
+        assert!(dot.starts_with("digraph BinaryTree {\n"));
+        assert!(dot.ends_with("}\n"));
+        assert!(dot.contains("[label=\"4: d\\nn = 6\"]"));
+        assert!(dot.contains("[label=\"3: c\\nn = 1\"]"));
+        assert_eq!(6, dot.matches("[label=").count());
+        // 6 has only a left child, so a point keeps it on the left
+        assert_eq!(1, dot.matches("[shape=point]").count());
+        assert_eq!(6, dot.matches(" -> ").count());
+
+        let mut quoted = BinaryTree::new();
+        quoted.put("say \"hi\"", 1);
+        assert_counts(&quoted);
+        assert!(quoted.to_dot().contains("[label=\"say \\\"hi\\\": 1\\nn = 1\"]"));
+    }
+
+    #[test]
+    fn percentile() {
+        let mut b = BinaryTree::new();
+        for i in 0..100 {
+            b.put(i * 2, i);
+            assert_counts(&b);
+        }
+        // 95th percentile of 100 keys is the one with rank 95
+        assert_eq!(Some(&190), b.select(95));
+        assert_eq!(Some(&4), b.ceiling(&3));
+        assert_eq!(Some(&2), b.floor(&3));
+        assert_eq!(5, b.size_between(&10, &20));
+        assert_eq!(0, b.size_between(&20, &10));
+        assert!(b.keys_in_range(&20, &10).is_empty());
+    }
 }
@@ -5,9 +5,9 @@
  * the upper squares to be connected to one of the lower squares?
  */
 
-mod util;
+use util;
 
-use union_find::*;
+use union_find::DisjointSet;
 
 use std::fmt;
 
@@ -41,9 +41,9 @@ struct Row {
 
 impl fmt::Debug for Row {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f,"|");
+        write!(f,"|")?;
         for sq in self.sqs.iter() {
-            write!(f, "{:?}", sq);
+            write!(f, "{:?}", sq)?;
         }
         write!(f,"|")
     }
@@ -56,14 +56,14 @@ struct Grid {
 
 impl fmt::Debug for Grid {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "\n");
+        writeln!(f)?;
         for i in 0..self.rows.len() {
-            write!(f, "{:?}", self.rows[i]);
+            write!(f, "{:?}", self.rows[i])?;
             if i != (self.rows.len() - 1) {
-                write!(f, "\n");
+                writeln!(f)?;
             }
         }
-        write!(f, "\n")
+        writeln!(f)
     }
 }
 
@@ -84,22 +84,22 @@ fn neighbour(c: &Coords, dir: &Dir) -> Coords {
     let coords = *c;
     match *dir {
         Dir::Left => if coords.0 == 0 {
-            coords.clone()
+            coords
         } else {
             Coords(coords.0 - 1,coords.1)
         },
         Dir::Right => if coords.0 == bound {
-            coords.clone()
+            coords
         } else {
             Coords(coords.0 + 1,coords.1)
         },
         Dir::Up => if coords.1 == 0 {
-            coords.clone()
+            coords
         } else {
             Coords(coords.0, coords.1 - 1)
         },
         Dir::Down => if coords.1 == bound {
-            coords.clone()
+            coords
         } else {
             Coords(coords.0, coords.1 + 1)
         },
@@ -123,14 +123,14 @@ fn coord_to_idx(coords: &Coords) -> usize {
     out
 }
 
-fn set_sym_white(grid: &mut Grid, sets: &mut Sets, coords: &Coords) {
+fn set_sym_white<S: DisjointSet>(grid: &mut Grid, sets: &mut S, coords: &Coords) {
     // Ensures the Grid and the Sets are mutated in sync
-    set_white(grid, &coords);
-    let idx = coord_to_idx(&coords);
+    set_white(grid, coords);
+    let idx = coord_to_idx(coords);
     for d in DIRS.iter() {
-        let d_coords = neighbour(&coords, d);
-        match get_color(&grid, &d_coords) {
-            Square::White => pc_quick_union::union(sets, idx, coord_to_idx(&d_coords)),
+        let d_coords = neighbour(coords, d);
+        match get_color(grid, &d_coords) {
+            Square::White => { sets.union(idx, coord_to_idx(&d_coords)); },
             Square::Black => ()
         }
     }
@@ -144,7 +144,7 @@ fn non_white_coords(grid: &Grid) -> Coords {
     coords
 }
 
-fn percolates(grid: &Grid, sets: &mut Sets) -> bool {
+fn percolates<S: DisjointSet>(grid: &Grid, sets: &mut S) -> bool {
     // Checks if any of the top squares is connected to any of
     // the lower squares by following a white path (no diagonals).
     let mut out = false;
@@ -156,7 +156,7 @@ fn percolates(grid: &Grid, sets: &mut Sets) -> bool {
                 let top_idx = coord_to_idx(&top_coords);
                 let low_idx = coord_to_idx(&low_coords);
                 match get_color(grid, &low_coords) {
-                    Square::White => if pc_quick_union::connected(sets, top_idx, low_idx) {
+                    Square::White => if sets.connected(top_idx, low_idx) {
                         out = true;
                         break
                     },
@@ -172,15 +172,13 @@ fn percolates(grid: &Grid, sets: &mut Sets) -> bool {
     out
 }
 
-type Sets = [Node; (L * L)];
-
-fn sym() -> usize {
+fn sym<S: DisjointSet>() -> usize {
     /* We will build the sets and the grid simultaneously and stop
      * when it percolates, returning how many white squares it took
      */
 
     // Start empty set
-    let mut sets: Sets = [Node::Root(1); (L * L)];
+    let mut sets = S::new(L * L);
     // Make grid
     let mut grid: Grid = Grid { rows: [Row { sqs: [Square::Black; L]}; L] };
 
@@ -191,17 +189,43 @@ fn sym() -> usize {
         set_sym_white(&mut grid, &mut sets, &coords);
         let perco = percolates(&grid, &mut sets);
         done = perco || (i == L * L);
-        i = i + 1;
+        i += 1;
     }
     i
 }
 
-pub fn constant() {
-    const N: usize = 1000; // number of simulations
+/// Average proportion of white squares at which the grid percolates
+/// over n simulations, using the disjoint sets strategy S
+pub fn proportion<S: DisjointSet>(n: usize) -> f64 {
     let mut sum: usize = 0;
-    for i in 0..N {
-        sum = sum + sym();
+    for _ in 0..n {
+        sum += sym::<S>();
     }
     // Probably missing decimals in the int to float casting
-    println!("The average proportion of white squares for percolation is {}", (sum as f64)/((N * L * L) as f64));
+    (sum as f64)/((n * L * L) as f64)
+}
+
+pub fn constant<S: DisjointSet>() {
+    const N: usize = 1000; // number of simulations
+    println!("The average proportion of white squares for percolation is {}", proportion::<S>(N));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::proportion;
+    use union_find::*;
+
+    #[test]
+    fn every_strategy_percolates_near_the_threshold() {
+        // The threshold for large grids is about 0.593
+        const N: usize = 50;
+        let ps = [proportion::<quick_find::QuickFind>(N),
+                  proportion::<quick_union::QuickUnion>(N),
+                  proportion::<b_quick_union::BalancedQuickUnion>(N),
+                  proportion::<pc_quick_union::CompressedQuickUnion>(N),
+                  proportion::<UnionFind>(N)];
+        for p in ps.iter() {
+            assert!(0.5 < *p && *p < 0.7, "{}", p);
+        }
+    }
 }
@@ -0,0 +1,278 @@
+/* Persistent binary search tree
+ * Nodes are never mutated once built and are shared through Rc, so
+ * put and delete leave the tree they are called on untouched: they
+ * copy only the nodes on the path from the root to the key and point
+ * the copies at the old, unchanged subtrees. Keeping a snapshot is
+ * then as cheap as keeping a root.
+ */
+#![allow(dead_code)]
+
+use std::rc::Rc;
+
+type Link<K, V> = Option<Rc<Node<K, V>>>;
+
+struct Node<K: Ord, V> {
+    key: K,
+    val: V,
+    n: u32,
+    left: Link<K, V>,
+    right: Link<K, V>,
+}
+
+fn size<K: Ord, V>(link: &Link<K, V>) -> u32 {
+    match *link {
+        None => 0,
+        Some(ref node) => node.n,
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> Node<K, V> {
+    fn new(key: K, val: V, left: Link<K, V>, right: Link<K, V>) -> Rc<Self> {
+        Rc::new(Node {
+            n: 1 + size(&left) + size(&right),
+            key,
+            val,
+            left,
+            right,
+        })
+    }
+    /// Copy of this node with one of its children replaced
+    fn with_child(&self, went_left: bool, child: Link<K, V>) -> Rc<Self> {
+        let (left, right) = if went_left {
+            (child, self.right.clone())
+        } else {
+            (self.left.clone(), child)
+        };
+        Node::new(self.key.clone(), self.val.clone(), left, right)
+    }
+}
+
+/// Rebuilds the path (from the root down, with the direction taken at
+/// each node) above a new subtree, sharing everything off the path.
+fn rebuild<K: Ord + Clone, V: Clone>(path: Vec<(&Rc<Node<K, V>>, bool)>,
+                                     mut link: Link<K, V>) -> Link<K, V> {
+    for (node, went_left) in path.into_iter().rev() {
+        link = Some(node.with_child(went_left, link));
+    }
+    link
+}
+
+/// Copy of the subtree without its minimum, and the minimum itself
+fn delete_min<K: Ord + Clone, V: Clone>(node: &Rc<Node<K, V>>)
+                                        -> (Link<K, V>, &Rc<Node<K, V>>) {
+    let mut path = Vec::new();
+    let mut min = node;
+    while let Some(ref left) = min.left {
+        path.push((min, true));
+        min = left;
+    }
+    (rebuild(path, min.right.clone()), min)
+}
+
+pub struct PersistentTree<K: Ord, V> {
+    root: Link<K, V>,
+}
+
+impl<K: Ord, V> Clone for PersistentTree<K, V> {
+    fn clone(&self) -> Self {
+        // O(1): the versions share every node
+        PersistentTree { root: self.root.clone() }
+    }
+}
+
+impl<K: Ord + Clone, V: Clone> PersistentTree<K, V> {
+    pub fn new() -> Self {
+        PersistentTree { root: None }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+    pub fn count(&self) -> u32 {
+        size(&self.root)
+    }
+    /// New version with key set to val
+    pub fn put(&self, key: K, val: V) -> Self {
+        let mut path = Vec::new();
+        let mut link = &self.root;
+        while let Some(ref node) = *link {
+            if node.key == key {
+                break;
+            }
+            let went_left = key < node.key;
+            path.push((node, went_left));
+            link = if went_left { &node.left } else { &node.right };
+        }
+        let (left, right) = match *link {
+            None => (None, None),
+            Some(ref node) => (node.left.clone(), node.right.clone()),
+        };
+        let leaf = Node::new(key, val, left, right);
+        PersistentTree { root: rebuild(path, Some(leaf)) }
+    }
+    /// New version without key
+    pub fn delete(&self, key: &K) -> Self {
+        let mut path = Vec::new();
+        let mut link = &self.root;
+        while let Some(ref node) = *link {
+            if node.key == *key {
+                break;
+            }
+            let went_left = *key < node.key;
+            path.push((node, went_left));
+            link = if went_left { &node.left } else { &node.right };
+        }
+        let replacement = match *link {
+            None => return self.clone(), // nothing should be deleted
+            Some(ref node) => match (&node.left, &node.right) {
+                (None, _) => node.right.clone(),
+                (_, None) => node.left.clone(),
+                (_, Some(right)) => {
+                    // Hibbard deletion with the successor
+                    let (right, min) = delete_min(right);
+                    let left = node.left.clone();
+                    Some(Node::new(min.key.clone(), min.val.clone(), left, right))
+                },
+            },
+        };
+        PersistentTree { root: rebuild(path, replacement) }
+    }
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let mut link = &self.root;
+        while let Some(ref node) = *link {
+            if node.key == *key {
+                return Some(&node.val);
+            }
+            link = if *key < node.key { &node.left } else { &node.right };
+        }
+        None
+    }
+    pub fn min_key(&self) -> Option<&K> {
+        let mut out = None;
+        let mut link = &self.root;
+        while let Some(ref node) = *link {
+            out = Some(&node.key);
+            link = &node.left;
+        }
+        out
+    }
+    pub fn max_key(&self) -> Option<&K> {
+        let mut out = None;
+        let mut link = &self.root;
+        while let Some(ref node) = *link {
+            out = Some(&node.key);
+            link = &node.right;
+        }
+        out
+    }
+    pub fn floor(&self, key: &K) -> Option<&K> {
+        let mut out = None;
+        let mut link = &self.root;
+        while let Some(ref node) = *link {
+            if node.key == *key {
+                return Some(&node.key);
+            } else if *key < node.key {
+                link = &node.left;
+            } else {
+                out = Some(&node.key);
+                link = &node.right;
+            }
+        }
+        out
+    }
+}
+
+impl<K: Ord, V> Drop for PersistentTree<K, V> {
+    fn drop(&mut self) {
+        // Only free the nodes no other version points to, without
+        // recursing once per level
+        let mut stack = Vec::new();
+        stack.extend(self.root.take());
+        while let Some(rc) = stack.pop() {
+            if let Ok(mut node) = Rc::try_unwrap(rc) {
+                stack.extend(node.left.take());
+                stack.extend(node.right.take());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PersistentTree;
+    use std::rc::Rc;
+    use util;
+
+    #[test]
+    fn old_versions_stay_queryable() {
+        let mut versions = vec![PersistentTree::new()];
+        for i in 0..20 {
+            let next = versions[i].put(i, i * 10);
+            versions.push(next);
+        }
+        for (i, t) in versions.iter().enumerate() {
+            assert_eq!(i as u32, t.count());
+            assert_eq!(i.checked_sub(1), t.max_key().cloned());
+            assert_eq!(None, t.get(&i));
+        }
+        let last = versions[20].clone();
+        let fewer = last.delete(&10).delete(&0).delete(&100);
+        assert_eq!(18, fewer.count());
+        assert_eq!(Some(&1), fewer.min_key());
+        assert_eq!(Some(&9), fewer.floor(&10));
+        assert_eq!(Some(&10), last.floor(&10));
+        assert_eq!(Some(&100), last.get(&10));
+        let updated = last.put(10, 0);
+        assert_eq!(Some(&0), updated.get(&10));
+        assert_eq!(Some(&100), last.get(&10));
+        assert_eq!(20, updated.count());
+    }
+
+    #[test]
+    fn shares_unchanged_subtrees() {
+        let mut t = PersistentTree::new();
+        for k in [50, 25, 75, 10, 30, 60, 90].iter() {
+            t = t.put(*k, ());
+        }
+        // 60 is on the left of 75, so the copy leaves 25's side alone
+        let u = t.delete(&60);
+        let (old_root, new_root) = (t.root.as_ref().unwrap(), u.root.as_ref().unwrap());
+        assert!(!Rc::ptr_eq(old_root, new_root));
+        assert!(Rc::ptr_eq(old_root.left.as_ref().unwrap(), new_root.left.as_ref().unwrap()));
+        assert_eq!(7, t.count());
+        assert_eq!(6, u.count());
+        // deleting a node with two children uses its successor
+        let w = u.delete(&50);
+        assert_eq!(Some(&75), w.root.as_ref().map(|node| &node.key));
+        assert_eq!(5, w.count());
+        assert_eq!(Some(&50), u.root.as_ref().map(|node| &node.key));
+    }
+
+    #[test]
+    fn random_versions() {
+        const N: usize = 200;
+        let mut t = PersistentTree::new();
+        let mut versions = Vec::new();
+        let mut model = Vec::new();
+        for _ in 0..N {
+            let key = util::rand_upto(N / 2);
+            t = if util::rand_upto(3) == 0 {
+                model.retain(|k| *k != key);
+                t.delete(&key)
+            } else {
+                if !model.contains(&key) {
+                    model.push(key);
+                }
+                t.put(key, key)
+            };
+            versions.push((t.clone(), model.clone()));
+        }
+        for (t, keys) in versions.iter() {
+            assert_eq!(keys.len() as u32, t.count());
+            for k in 0..(N / 2) {
+                assert_eq!(keys.contains(&k), t.get(&k).is_some());
+            }
+            assert_eq!(keys.iter().min(), t.min_key());
+            assert_eq!(keys.iter().max(), t.max_key());
+        }
+    }
+}
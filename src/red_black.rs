@@ -0,0 +1,433 @@
+/* Left-leaning red-black tree
+ * Same symbol table as BinaryTree, but every 3-node of a 2-3 tree is
+ * encoded as two nodes joined by a red link leaning left. Rotations
+ * and color flips on the way back up from put/delete keep the tree
+ * black-balanced, so the height is at most 2 lg(n) even when the
+ * keys arrive sorted.
+ *
+ * Nodes can carry more about their subtree than its size n: an
+ * Augment is recomputed from a node's key and its children's whenever
+ * a rotation or balance changes them. IntervalTree keeps the largest
+ * end point below each node that way.
+ */
+#![allow(dead_code)]
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Color {
+    Red,
+    Black,
+}
+
+impl Color {
+    fn flip(self) -> Color {
+        match self {
+            Color::Red => Color::Black,
+            Color::Black => Color::Red,
+        }
+    }
+}
+
+/// What a node knows about its subtree besides its size, rebuilt from
+/// its key and its children's whenever they change
+pub trait Augment<K> {
+    fn update(key: &K, left: Option<&Self>, right: Option<&Self>) -> Self;
+}
+
+/// Plain symbol tables keep nothing extra
+impl<K> Augment<K> for () {
+    fn update(_: &K, _: Option<&()>, _: Option<&()>) {}
+}
+
+pub type Link<K, V, A> = Option<Box<Node<K, V, A>>>;
+
+pub struct Node<K: Ord, V, A: Augment<K>> {
+    pub key: K,
+    pub val: V,
+    pub n: u32,
+    pub aug: A,
+    color: Color, // color of the link from the parent
+    pub left: Link<K, V, A>,
+    pub right: Link<K, V, A>,
+}
+
+impl<K: Ord, V, A: Augment<K>> Node<K, V, A> {
+    fn new(key: K, val: V) -> Self {
+        Node {
+            aug: A::update(&key, None, None),
+            key,
+            val,
+            n: 1,
+            color: Color::Red, // new nodes always join their parent
+            left: None,
+            right: None,
+        }
+    }
+    /// Recomputes n and aug from the children
+    fn update(&mut self) {
+        self.n = 1 + size(&self.left) + size(&self.right);
+        self.aug = A::update(&self.key,
+                             self.left.as_ref().map(|node| &node.aug),
+                             self.right.as_ref().map(|node| &node.aug));
+    }
+}
+
+fn is_red<K: Ord, V, A: Augment<K>>(link: &Link<K, V, A>) -> bool {
+    match *link {
+        None => false, // null links are black
+        Some(ref node) => node.color == Color::Red,
+    }
+}
+
+fn is_left_red<K: Ord, V, A: Augment<K>>(link: &Link<K, V, A>) -> bool {
+    match *link {
+        None => false,
+        Some(ref node) => is_red(&node.left),
+    }
+}
+
+fn size<K: Ord, V, A: Augment<K>>(link: &Link<K, V, A>) -> u32 {
+    match *link {
+        None => 0,
+        Some(ref node) => node.n,
+    }
+}
+
+fn rotate_left<K: Ord, V, A: Augment<K>>(mut h: Box<Node<K, V, A>>) -> Box<Node<K, V, A>> {
+    // Make a right-leaning red link lean left
+    let mut x = h.right.take().expect("rotate_left without right child");
+    h.right = x.left.take();
+    x.color = h.color;
+    h.color = Color::Red;
+    h.update();
+    x.left = Some(h);
+    x.update();
+    x
+}
+
+fn rotate_right<K: Ord, V, A: Augment<K>>(mut h: Box<Node<K, V, A>>) -> Box<Node<K, V, A>> {
+    // Make a left-leaning red link lean right
+    let mut x = h.left.take().expect("rotate_right without left child");
+    h.left = x.right.take();
+    x.color = h.color;
+    h.color = Color::Red;
+    h.update();
+    x.right = Some(h);
+    x.update();
+    x
+}
+
+fn flip_colors<K: Ord, V, A: Augment<K>>(h: &mut Node<K, V, A>) {
+    // Split (or merge) a temporary 4-node
+    h.color = h.color.flip();
+    if let Some(ref mut left) = h.left {
+        left.color = left.color.flip();
+    }
+    if let Some(ref mut right) = h.right {
+        right.color = right.color.flip();
+    }
+}
+
+fn balance<K: Ord, V, A: Augment<K>>(mut h: Box<Node<K, V, A>>) -> Box<Node<K, V, A>> {
+    // Restore the left-leaning invariants on the way up
+    if is_red(&h.right) && !is_red(&h.left) {
+        h = rotate_left(h);
+    }
+    if is_red(&h.left) && is_left_red(&h.left) {
+        h = rotate_right(h);
+    }
+    if is_red(&h.left) && is_red(&h.right) {
+        flip_colors(&mut h);
+    }
+    h.update();
+    h
+}
+
+fn move_red_left<K: Ord, V, A: Augment<K>>(mut h: Box<Node<K, V, A>>) -> Box<Node<K, V, A>> {
+    // Assuming h is red and both h.left and h.left.left are black,
+    // make h.left or one of its children red.
+    flip_colors(&mut h);
+    if is_left_red(&h.right) {
+        h.right = h.right.take().map(rotate_right);
+        h = rotate_left(h);
+        flip_colors(&mut h);
+    }
+    h
+}
+
+fn move_red_right<K: Ord, V, A: Augment<K>>(mut h: Box<Node<K, V, A>>) -> Box<Node<K, V, A>> {
+    // Assuming h is red and both h.right and h.right.left are black,
+    // make h.right or one of its children red.
+    flip_colors(&mut h);
+    if is_left_red(&h.left) {
+        h = rotate_right(h);
+        flip_colors(&mut h);
+    }
+    h
+}
+
+fn put<K: Ord, V, A: Augment<K>>(link: Link<K, V, A>, key: K, val: V) -> Box<Node<K, V, A>> {
+    match link {
+        None => Box::new(Node::new(key, val)),
+        Some(mut h) => {
+            if h.key == key {
+                h.val = val;
+            } else if key < h.key {
+                h.left = Some(put(h.left.take(), key, val));
+            } else {
+                h.right = Some(put(h.right.take(), key, val));
+            }
+            balance(h)
+        }
+    }
+}
+
+/// Returns the subtree without its minimum, and the detached minimum.
+fn delete_min<K: Ord, V, A: Augment<K>>(mut h: Box<Node<K, V, A>>) -> (Link<K, V, A>, Node<K, V, A>) {
+    if h.left.is_none() {
+        return (None, *h);
+    }
+    if !is_red(&h.left) && !is_left_red(&h.left) {
+        h = move_red_left(h);
+    }
+    let (left, min) = delete_min(h.left.take().expect("left checked above"));
+    h.left = left;
+    (Some(balance(h)), min)
+}
+
+/// Assumes the key is present in the subtree rooted at h.
+fn delete<K: Ord, V, A: Augment<K>>(mut h: Box<Node<K, V, A>>, key: &K) -> Link<K, V, A> {
+    if *key < h.key {
+        if !is_red(&h.left) && !is_left_red(&h.left) {
+            h = move_red_left(h);
+        }
+        h.left = h.left.take().and_then(|left| delete(left, key));
+    } else {
+        if is_red(&h.left) {
+            h = rotate_right(h);
+        }
+        if h.key == *key && h.right.is_none() {
+            return None;
+        }
+        if !is_red(&h.right) && !is_left_red(&h.right) {
+            h = move_red_right(h);
+        }
+        if h.key == *key {
+            // replace with the successor, Hibbard style
+            let (right, min) = delete_min(h.right.take().expect("right checked above"));
+            h.key = min.key;
+            h.val = min.val;
+            h.right = right;
+        } else {
+            h.right = h.right.take().and_then(|right| delete(right, key));
+        }
+    }
+    Some(balance(h))
+}
+
+fn height<K: Ord, V, A: Augment<K>>(link: &Link<K, V, A>) -> u32 {
+    // Recursion is fine here: the depth is bounded by 2 lg(n)
+    match *link {
+        None => 0,
+        Some(ref node) => 1 + height(&node.left).max(height(&node.right)),
+    }
+}
+
+pub struct RedBlackTree<K: Ord, V, A: Augment<K> = ()> {
+    root: Link<K, V, A>,
+}
+
+impl<K: Ord, V> RedBlackTree<K, V> {
+    pub fn new() -> Self {
+        RedBlackTree::augmented()
+    }
+}
+
+impl<K: Ord, V, A: Augment<K>> RedBlackTree<K, V, A> {
+    /// An empty tree keeping A in every node
+    pub fn augmented() -> Self {
+        RedBlackTree { root: None }
+    }
+    pub fn root(&self) -> &Link<K, V, A> {
+        &self.root
+    }
+    pub fn is_empty(&self) -> bool {
+        self.root.is_none()
+    }
+    pub fn count(&self) -> u32 {
+        size(&self.root)
+    }
+    /// Number of nodes on the longest root to leaf path
+    pub fn height(&self) -> u32 {
+        height(&self.root)
+    }
+    pub fn put(&mut self, key: K, val: V) {
+        let mut root = put(self.root.take(), key, val);
+        root.color = Color::Black;
+        self.root = Some(root);
+    }
+    pub fn get(&self, key: &K) -> Option<&V> {
+        let mut link = &self.root;
+        while let Some(ref node) = *link {
+            if node.key == *key {
+                return Some(&node.val);
+            } else if *key < node.key {
+                link = &node.left;
+            } else {
+                link = &node.right;
+            }
+        }
+        None
+    }
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let mut link = &mut self.root;
+        while let Some(ref mut node) = *link {
+            if node.key == *key {
+                return Some(&mut node.val);
+            } else if *key < node.key {
+                link = &mut node.left;
+            } else {
+                link = &mut node.right;
+            }
+        }
+        None
+    }
+    pub fn min_key(&self) -> Option<&K> {
+        let mut out = None;
+        let mut link = &self.root;
+        while let Some(ref node) = *link {
+            out = Some(&node.key);
+            link = &node.left;
+        }
+        out
+    }
+    pub fn max_key(&self) -> Option<&K> {
+        let mut out = None;
+        let mut link = &self.root;
+        while let Some(ref node) = *link {
+            out = Some(&node.key);
+            link = &node.right;
+        }
+        out
+    }
+    pub fn floor(&self, key: &K) -> Option<&K> {
+        // Largest key <= key: every time we go right, the current
+        // node is the best candidate so far.
+        let mut out = None;
+        let mut link = &self.root;
+        while let Some(ref node) = *link {
+            if node.key == *key {
+                return Some(&node.key);
+            } else if *key < node.key {
+                link = &node.left;
+            } else {
+                out = Some(&node.key);
+                link = &node.right;
+            }
+        }
+        out
+    }
+    pub fn delete(&mut self, key: &K) {
+        if self.get(key).is_none() {
+            return; // nothing should be deleted
+        }
+        if let Some(mut root) = self.root.take() {
+            // if both children of root are black, set root to red
+            if !is_red(&root.left) && !is_red(&root.right) {
+                root.color = Color::Red;
+            }
+            self.root = delete(root, key);
+        }
+        if let Some(ref mut root) = self.root {
+            root.color = Color::Black;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Augment, Color, Link, RedBlackTree};
+    use util;
+
+    // Returns the number of black links to every leaf, or panics if
+    // the tree is not a valid left-leaning red-black tree.
+    fn black_height<K: Ord, V, A: Augment<K>>(link: &Link<K, V, A>) -> u32 {
+        match *link {
+            None => 0,
+            Some(ref node) => {
+                assert!(node.color == Color::Black || !super::is_red(&node.left));
+                assert!(!super::is_red(&node.right));
+                let left = black_height(&node.left);
+                assert_eq!(left, black_height(&node.right));
+                match node.color {
+                    Color::Black => left + 1,
+                    Color::Red => left,
+                }
+            }
+        }
+    }
+
+    fn assert_balanced<K: Ord, V, A: Augment<K>>(t: &RedBlackTree<K, V, A>) {
+        black_height(&t.root);
+        let lg = 32 - (t.count() + 1).leading_zeros();
+        assert!(t.height() <= 2 * lg);
+    }
+
+    #[test]
+    fn sorted_inserts() {
+        const N: usize = 1000;
+        let mut t = RedBlackTree::new();
+        assert!(t.is_empty());
+        for i in 0..N {
+            t.put(i, i);
+            assert_balanced(&t);
+            assert_eq!(Some(&0), t.min_key());
+            assert_eq!(Some(&i), t.max_key());
+        }
+        assert_eq!(N as u32, t.count());
+        for i in 0..N {
+            assert_eq!(Some(&i), t.get(&i));
+        }
+        assert_eq!(Some(&(N - 1)), t.floor(&(2 * N)));
+    }
+
+    #[test]
+    fn random_inserts_and_deletes() {
+        const N: usize = 500;
+        let mut t = RedBlackTree::new();
+        let v = util::rand_vec(N);
+        for x in v.iter() {
+            t.put(*x, *x * 2);
+            assert_balanced(&t);
+        }
+        for x in v.iter() {
+            assert_eq!(Some(&(*x * 2)), t.get(x));
+        }
+        for x in v.iter() {
+            t.delete(x);
+            assert_eq!(None, t.get(x));
+            assert_balanced(&t);
+        }
+        assert!(t.is_empty());
+    }
+
+    #[test]
+    fn floor_and_delete() {
+        let mut t = RedBlackTree::new();
+        for i in 0..10 {
+            t.put(i * 10, i);
+        }
+        assert_eq!(None, t.floor(&-1));
+        assert_eq!(Some(&0), t.floor(&9));
+        assert_eq!(Some(&50), t.floor(&55));
+        t.delete(&50);
+        assert_eq!(Some(&40), t.floor(&55));
+        assert_eq!(9, t.count());
+        t.delete(&55); // absent keys are ignored
+        assert_eq!(9, t.count());
+        if let Some(val) = t.get_mut(&90) {
+            *val = 0;
+        }
+        assert_eq!(Some(&0), t.get(&90));
+        assert_balanced(&t);
+    }
+}
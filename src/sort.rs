@@ -1,6 +1,7 @@
 // Selection sort
+#![allow(dead_code)]
 
-mod util;
+use util;
 
 use std::fmt;
 
@@ -23,7 +24,7 @@ pub fn insertion<T: Ord>(xs: &mut [T]) {
         let mut j = i;
         while (0 < j) && (xs[j] < xs[j-1]) {
             xs.swap(j-1, j);
-            j = j - 1;
+            j -= 1;
         }
     }
 }
@@ -40,11 +41,11 @@ pub fn shell<T :Ord>(xs: &mut [T]) {
             let mut j = i;
             while (h <= j) && (xs[j] < xs[j-h]) {
                 xs.swap(j-h, j);
-                j = j - h;
+                j -= h;
             }
-            i = i + h;
+            i += h;
         }
-        h = h/3;
+        h /= 3;
     }
 }
 
@@ -82,14 +83,14 @@ fn merge<T: Ord + Copy + fmt::Debug>(a: &[T], b: &[T]) -> Vec<T> {
         let bj = unsafe { b.get_unchecked(j) };
         if ai < bj {
             out.push(*ai);
-            i = i + 1;
+            i += 1;
             // we are done with a, copy what's left of b in out
             if i == na {
                 append_from(b, j, &mut out);
             }
         } else {
             out.push(*bj);
-            j = j + 1;
+            j += 1;
             if j == nb {
                 append_from(a, i, &mut out);
             }
@@ -102,9 +103,7 @@ fn merge<T: Ord + Copy + fmt::Debug>(a: &[T], b: &[T]) -> Vec<T> {
 fn merge_sort<T: Ord + Copy + fmt::Debug>(v: &[T]) -> Vec<T> {
     let n = v.len();
     if n == 1 {
-        let mut out = Vec::with_capacity(1);
-        out.push(v[0]);
-        out
+        vec![v[0]]
     } else {
         let slices = v.split_at(n/2);
         let a = slices.0;
@@ -113,7 +112,7 @@ fn merge_sort<T: Ord + Copy + fmt::Debug>(v: &[T]) -> Vec<T> {
     }
 }
 
-fn partition<T: Ord>(v: &mut Vec<T>, lo: usize, hi: usize) -> usize {
+fn partition<T: Ord>(v: &mut [T], lo: usize, hi: usize) -> usize {
     assert!(lo < hi);
     // grabs the first value
     let mut i = lo;
@@ -121,11 +120,11 @@ fn partition<T: Ord>(v: &mut Vec<T>, lo: usize, hi: usize) -> usize {
     while i < j {
         // find the first value that is bigger than p from the beginning
         while i < hi && v[i] <= v[lo] {
-            i = i + 1;
+            i += 1;
         }
         // we have a value that is bigger than p
         while lo < j && v[lo] <= v[j] {
-            j = j - 1;
+            j -= 1;
         }
         // we a value that is smaller than p
         // then we have two values that are out of place. exchange them
@@ -137,7 +136,7 @@ fn partition<T: Ord>(v: &mut Vec<T>, lo: usize, hi: usize) -> usize {
     j
 }
 
-fn quick_sort_rec<T: Ord>(v: &mut Vec<T>, lo: usize, hi: usize) {
+fn quick_sort_rec<T: Ord>(v: &mut [T], lo: usize, hi: usize) {
     if lo < hi {
         let j = partition(v, lo, hi);
         if j > 0 {
@@ -149,7 +148,7 @@ fn quick_sort_rec<T: Ord>(v: &mut Vec<T>, lo: usize, hi: usize) {
     }
 }
 
-fn quick_sort<T: Ord>(v: &mut Vec<T>) {
+fn quick_sort<T: Ord>(v: &mut [T]) {
     // TODO: add shuffling
     let n = v.len();
     quick_sort_rec(v, 0, n - 1);
@@ -158,15 +157,17 @@ fn quick_sort<T: Ord>(v: &mut Vec<T>) {
 
 #[cfg(test)]
 mod tests {
+    use super::*;
+    use util;
 
     const N: usize = 10;
 
 
     #[test]
     fn step_test() {
-        let mut v = util::rand_vec(N);
-        assert_eq!(vec![1,2,3,4,5,6], merge(&vec![1,4,5],&vec![2,3,6]));
-        assert_eq!(vec![1,3,4,5,6,8], merge_sort(&vec![1,8,3,4,5,6]));
+        let v = util::rand_vec(N);
+        assert_eq!(vec![1,2,3,4,5,6], merge(&[1,4,5],&[2,3,6]));
+        assert_eq!(vec![1,3,4,5,6,8], merge_sort(&[1,8,3,4,5,6]));
         assert!(util::is_sorted(&merge_sort(&v)));
         let mut u = vec![1,3,5,2,4,6];
         let n = u.len();
@@ -221,7 +222,7 @@ mod tests {
 
     #[test]
     fn multi_test() {
-        for i in 0..100 {
+        for _ in 0..100 {
             let mut v = util::rand_vec(N);
             quick_sort(&mut v);
             assert!(util::is_sorted(&v));
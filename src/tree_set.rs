@@ -0,0 +1,366 @@
+/* Ordered sets on top of BinaryTree
+ * A TreeSet is a BinaryTree whose values carry nothing, and a
+ * TreeMultiset one whose values count the copies of each key. Since
+ * both trees iterate in sorted order, the set algebra walks the two
+ * in-order traversals side by side, like the merge in merge sort,
+ * and never looks anything up: linear in the size of both sets.
+ */
+#![allow(dead_code)]
+
+use std::cmp::{self, Ordering};
+use std::iter::{FromIterator, Peekable};
+
+use super::{BinaryTree, Iter};
+
+/// Walks two trees in order, yielding each key once together with its
+/// value in each tree it appears in.
+struct Merge<'a, K: Ord + 'a, V: 'a> {
+    a: Peekable<Iter<'a, K, V>>,
+    b: Peekable<Iter<'a, K, V>>,
+}
+
+impl<'a, K: Ord, V> Merge<'a, K, V> {
+    fn new(a: &'a BinaryTree<K, V>, b: &'a BinaryTree<K, V>) -> Self {
+        Merge {
+            a: a.iter().peekable(),
+            b: b.iter().peekable(),
+        }
+    }
+}
+
+impl<'a, K: Ord, V> Iterator for Merge<'a, K, V> {
+    type Item = (&'a K, Option<&'a V>, Option<&'a V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let order = match (self.a.peek(), self.b.peek()) {
+            (None, None) => return None,
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (Some(&(ka, _)), Some(&(kb, _))) => ka.cmp(kb),
+        };
+        match order {
+            Ordering::Less => self.a.next().map(|(k, v)| (k, Some(v), None)),
+            Ordering::Greater => self.b.next().map(|(k, v)| (k, None, Some(v))),
+            Ordering::Equal => {
+                let (k, va) = self.a.next()?;
+                let (_, vb) = self.b.next()?;
+                Some((k, Some(va), Some(vb)))
+            },
+        }
+    }
+}
+
+pub struct TreeSet<K: Ord> {
+    tree: BinaryTree<K, ()>,
+}
+
+impl<K: Ord> TreeSet<K> {
+    pub fn new() -> Self {
+        TreeSet { tree: BinaryTree::new() }
+    }
+    pub fn len(&self) -> u32 {
+        self.tree.count()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+    /// Returns false if the key was already there
+    pub fn insert(&mut self, key: K) -> bool {
+        self.tree.insert(key, ()).is_none()
+    }
+    /// Returns false if the key was not there
+    pub fn remove(&mut self, key: &K) -> bool {
+        self.tree.remove(key).is_some()
+    }
+    pub fn contains(&self, key: &K) -> bool {
+        self.tree.contains_key(key)
+    }
+    pub fn min(&self) -> Option<&K> {
+        self.tree.min_key()
+    }
+    pub fn max(&self) -> Option<&K> {
+        self.tree.max_key()
+    }
+    /// Keys in sorted order
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &K> {
+        self.tree.iter().map(|(k, _)| k)
+    }
+    /// Sorted keys in [lo, hi)
+    pub fn range(&self, lo: &K, hi: &K) -> impl Iterator<Item = &K> {
+        self.tree.range(lo, hi).map(|(k, _)| k)
+    }
+    /// Keys in either set
+    pub fn union<'a>(&'a self, other: &'a TreeSet<K>) -> impl Iterator<Item = &'a K> {
+        Merge::new(&self.tree, &other.tree).map(|(k, _, _)| k)
+    }
+    /// Keys in both sets
+    pub fn intersection<'a>(&'a self, other: &'a TreeSet<K>) -> impl Iterator<Item = &'a K> {
+        Merge::new(&self.tree, &other.tree).filter_map(|(k, a, b)| match (a, b) {
+            (Some(_), Some(_)) => Some(k),
+            _ => None,
+        })
+    }
+    /// Keys in self but not in other
+    pub fn difference<'a>(&'a self, other: &'a TreeSet<K>) -> impl Iterator<Item = &'a K> {
+        Merge::new(&self.tree, &other.tree).filter_map(|(k, a, b)| match (a, b) {
+            (Some(_), None) => Some(k),
+            _ => None,
+        })
+    }
+    /// Keys in exactly one of the sets
+    pub fn symmetric_difference<'a>(&'a self, other: &'a TreeSet<K>)
+                                    -> impl Iterator<Item = &'a K> {
+        Merge::new(&self.tree, &other.tree).filter_map(|(k, a, b)| match (a, b) {
+            (Some(_), Some(_)) => None,
+            _ => Some(k),
+        })
+    }
+    pub fn is_subset(&self, other: &TreeSet<K>) -> bool {
+        self.len() <= other.len() && self.difference(other).next().is_none()
+    }
+    pub fn is_superset(&self, other: &TreeSet<K>) -> bool {
+        other.is_subset(self)
+    }
+    pub fn is_disjoint(&self, other: &TreeSet<K>) -> bool {
+        self.intersection(other).next().is_none()
+    }
+}
+
+impl<K: Ord> FromIterator<K> for TreeSet<K> {
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Self {
+        let mut s = TreeSet::new();
+        s.extend(iter);
+        s
+    }
+}
+
+impl<K: Ord> Extend<K> for TreeSet<K> {
+    fn extend<I: IntoIterator<Item = K>>(&mut self, iter: I) {
+        for key in iter {
+            self.insert(key);
+        }
+    }
+}
+
+pub struct TreeMultiset<K: Ord> {
+    tree: BinaryTree<K, u32>, // copies of each key, never 0
+    len: u32,                 // copies of all keys
+}
+
+impl<K: Ord> TreeMultiset<K> {
+    pub fn new() -> Self {
+        TreeMultiset {
+            tree: BinaryTree::new(),
+            len: 0,
+        }
+    }
+    /// Number of elements, counting repeats
+    pub fn len(&self) -> u32 {
+        self.len
+    }
+    /// Number of different keys
+    pub fn distinct(&self) -> u32 {
+        self.tree.count()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.tree.is_empty()
+    }
+    pub fn insert(&mut self, key: K) {
+        *self.tree.entry(key).or_insert(0) += 1;
+        self.len += 1;
+    }
+    /// Removes one copy of key, returning false if there was none
+    pub fn remove(&mut self, key: &K) -> bool {
+        let left = match self.tree.get_mut(key) {
+            None => return false,
+            Some(copies) => {
+                *copies -= 1;
+                *copies
+            },
+        };
+        if left == 0 {
+            self.tree.delete(key);
+        }
+        self.len -= 1;
+        true
+    }
+    /// Copies of key in the multiset
+    pub fn count(&self, key: &K) -> u32 {
+        self.tree.get(key).cloned().unwrap_or(0)
+    }
+    pub fn contains(&self, key: &K) -> bool {
+        self.tree.contains_key(key)
+    }
+    /// Each key with its number of copies, in sorted order
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&K, u32)> {
+        self.tree.iter().map(|(k, n)| (k, *n))
+    }
+    /// Keys in [lo, hi) with their number of copies
+    pub fn range<'a>(&'a self, lo: &K, hi: &K) -> impl Iterator<Item = (&'a K, u32)> {
+        self.tree.range(lo, hi).map(|(k, n)| (k, *n))
+    }
+    // The multiset operations combine the copies of each key
+    fn combine<'a, F>(&'a self, other: &'a TreeMultiset<K>, f: F)
+                      -> impl Iterator<Item = (&'a K, u32)>
+        where F: Fn(u32, u32) -> u32 {
+        Merge::new(&self.tree, &other.tree).filter_map(move |(k, a, b)| {
+            let n = f(a.cloned().unwrap_or(0), b.cloned().unwrap_or(0));
+            if n == 0 { None } else { Some((k, n)) }
+        })
+    }
+    /// Largest number of copies of each key
+    pub fn union<'a>(&'a self, other: &'a TreeMultiset<K>) -> impl Iterator<Item = (&'a K, u32)> {
+        self.combine(other, cmp::max)
+    }
+    /// Smallest number of copies of each key
+    pub fn intersection<'a>(&'a self, other: &'a TreeMultiset<K>)
+                            -> impl Iterator<Item = (&'a K, u32)> {
+        self.combine(other, cmp::min)
+    }
+    /// Copies in self left after taking away those in other
+    pub fn difference<'a>(&'a self, other: &'a TreeMultiset<K>)
+                          -> impl Iterator<Item = (&'a K, u32)> {
+        self.combine(other, |a, b| a.saturating_sub(b))
+    }
+    /// How many more copies one side has than the other
+    pub fn symmetric_difference<'a>(&'a self, other: &'a TreeMultiset<K>)
+                                    -> impl Iterator<Item = (&'a K, u32)> {
+        self.combine(other, |a, b| cmp::max(a, b) - cmp::min(a, b))
+    }
+    pub fn is_subset(&self, other: &TreeMultiset<K>) -> bool {
+        self.len() <= other.len() && self.difference(other).next().is_none()
+    }
+    pub fn is_superset(&self, other: &TreeMultiset<K>) -> bool {
+        other.is_subset(self)
+    }
+    pub fn is_disjoint(&self, other: &TreeMultiset<K>) -> bool {
+        self.intersection(other).next().is_none()
+    }
+}
+
+impl<K: Ord> FromIterator<K> for TreeMultiset<K> {
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Self {
+        let mut s = TreeMultiset::new();
+        s.extend(iter);
+        s
+    }
+}
+
+impl<K: Ord> Extend<K> for TreeMultiset<K> {
+    fn extend<I: IntoIterator<Item = K>>(&mut self, iter: I) {
+        for key in iter {
+            self.insert(key);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TreeMultiset, TreeSet};
+    use std::collections::BTreeSet;
+    use util;
+
+    fn collect<'a, I: Iterator<Item = &'a u32>>(it: I) -> Vec<u32> {
+        it.cloned().collect()
+    }
+
+    #[test]
+    fn set_algebra() {
+        let a: TreeSet<u32> = vec![5, 1, 3, 7, 9].into_iter().collect();
+        let b: TreeSet<u32> = vec![3, 4, 5, 6].into_iter().collect();
+        assert_eq!(vec![1, 3, 4, 5, 6, 7, 9], collect(a.union(&b)));
+        assert_eq!(vec![3, 5], collect(a.intersection(&b)));
+        assert_eq!(vec![1, 7, 9], collect(a.difference(&b)));
+        assert_eq!(vec![4, 6], collect(b.difference(&a)));
+        assert_eq!(vec![1, 4, 6, 7, 9], collect(a.symmetric_difference(&b)));
+        assert_eq!(vec![3, 5, 7], collect(a.range(&2, &9)));
+        assert_eq!(vec![9, 7, 5, 3, 1], collect(a.iter().rev()));
+        let c: TreeSet<u32> = vec![3, 5].into_iter().collect();
+        assert!(c.is_subset(&a) && c.is_subset(&b));
+        assert!(a.is_superset(&c));
+        assert!(!a.is_subset(&b));
+        let d: TreeSet<u32> = vec![2, 8].into_iter().collect();
+        assert!(a.is_disjoint(&d));
+        assert!(!a.is_disjoint(&b));
+        assert!(TreeSet::new().is_subset(&d));
+    }
+
+    #[test]
+    fn matches_btree_set() {
+        const N: usize = 50;
+        for _ in 0..20 {
+            let xs = util::rand_vec(N);
+            let ys = util::rand_vec(N);
+            let a: TreeSet<usize> = xs.iter().cloned().collect();
+            let b: TreeSet<usize> = ys.iter().cloned().collect();
+            let sa: BTreeSet<usize> = xs.iter().cloned().collect();
+            let sb: BTreeSet<usize> = ys.iter().cloned().collect();
+            assert_eq!(sa.len() as u32, a.len());
+            assert!(a.union(&b).eq(sa.union(&sb)));
+            assert!(a.intersection(&b).eq(sa.intersection(&sb)));
+            assert!(a.difference(&b).eq(sa.difference(&sb)));
+            assert!(a.symmetric_difference(&b).eq(sa.symmetric_difference(&sb)));
+            assert_eq!(sa.is_subset(&sb), a.is_subset(&b));
+            assert_eq!(sa.is_disjoint(&sb), a.is_disjoint(&b));
+        }
+    }
+
+    #[test]
+    fn set_insert_remove() {
+        let mut s = TreeSet::new();
+        assert!(s.insert("b"));
+        assert!(s.insert("a"));
+        assert!(!s.insert("a"));
+        assert_eq!(Ok(()), s.tree.check_invariants());
+        assert_eq!(2, s.len());
+        assert_eq!(Some(&"a"), s.min());
+        assert_eq!(Some(&"b"), s.max());
+        assert!(s.remove(&"a"));
+        assert!(!s.remove(&"a"));
+        assert_eq!(Ok(()), s.tree.check_invariants());
+        assert!(!s.contains(&"a"));
+        assert!(s.contains(&"b"));
+    }
+
+    #[test]
+    fn multiset() {
+        let a: TreeMultiset<char> = "mississippi".chars().collect();
+        let b: TreeMultiset<char> = "misspelt".chars().collect();
+        assert_eq!(11, a.len());
+        assert_eq!(4, a.distinct());
+        assert_eq!(4, a.count(&'s'));
+        assert_eq!(0, a.count(&'x'));
+        let union: Vec<(&char, u32)> = a.union(&b).collect();
+        assert_eq!(vec![(&'e', 1), (&'i', 4), (&'l', 1), (&'m', 1),
+                        (&'p', 2), (&'s', 4), (&'t', 1)], union);
+        let inter: Vec<(&char, u32)> = a.intersection(&b).collect();
+        assert_eq!(vec![(&'i', 1), (&'m', 1), (&'p', 1), (&'s', 2)], inter);
+        let diff: Vec<(&char, u32)> = a.difference(&b).collect();
+        assert_eq!(vec![(&'i', 3), (&'p', 1), (&'s', 2)], diff);
+        let sym: Vec<(&char, u32)> = a.symmetric_difference(&b).collect();
+        assert_eq!(vec![(&'e', 1), (&'i', 3), (&'l', 1), (&'p', 1),
+                        (&'s', 2), (&'t', 1)], sym);
+        let range: Vec<(&char, u32)> = a.range(&'j', &'q').collect();
+        assert_eq!(vec![(&'m', 1), (&'p', 2)], range);
+        let small: TreeMultiset<char> = "sip".chars().collect();
+        assert!(small.is_subset(&a) && a.is_superset(&small));
+        assert!(!a.is_subset(&b));
+        let other: TreeMultiset<char> = "xyz".chars().collect();
+        assert!(a.is_disjoint(&other));
+    }
+
+    #[test]
+    fn multiset_remove() {
+        let mut m: TreeMultiset<u32> = vec![1, 1, 2].into_iter().collect();
+        assert!(m.remove(&1));
+        assert_eq!(1, m.count(&1));
+        assert!(m.remove(&1));
+        assert_eq!(Ok(()), m.tree.check_invariants());
+        assert!(!m.contains(&1));
+        assert!(!m.remove(&1));
+        assert_eq!(1, m.len());
+        assert_eq!(1, m.distinct());
+        let all: Vec<(&u32, u32)> = m.iter().collect();
+        assert_eq!(vec![(&2, 1)], all);
+    }
+}
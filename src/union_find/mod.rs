@@ -1,15 +1,168 @@
-/// Contains several implementations for disjoints sets following the
-/// interface:
-/// - union(set, a, b) -> void
-/// - connected(sets, a, b) -> bool
+// Contains several implementations for disjoints sets following the
+// interface:
+// - union(set, a, b) -> bool, false if they were already connected
+// - connected(sets, a, b) -> bool
+// over slices the caller sizes and initialises. Each strategy also
+// has a struct owning its sets behind the DisjointSet trait, so code
+// can be written once and switch strategies with a type parameter.
+// UnionFind is the one to use: it also grows at runtime.
 
-/* Could probably do it with traits, but I don't know how to make a
- * struct that has size known at runtime, but passed from somewhere
- * else
- * -> Change code to take size at run time (with Vec)
- */
+use std::collections::HashMap;
+use std::hash::Hash;
 
-extern crate rand; // used for testing
+/// Disjoint sets over the elements 0..n
+#[allow(dead_code)] // the binaries each call only part of it
+pub trait DisjointSet {
+    /// n elements, each in its own component
+    fn new(n: usize) -> Self;
+    /// Joins the components of a and b, returning false if they
+    /// were already the same one
+    fn union(&mut self, a: usize, b: usize) -> bool;
+    /// Representative of x's component. Takes &mut self since some
+    /// strategies restructure their trees while looking.
+    fn find(&mut self, x: usize) -> usize;
+    fn connected(&mut self, a: usize, b: usize) -> bool {
+        self.find(a) == self.find(b)
+    }
+    /// Number of components
+    fn count(&self) -> usize;
+    /// Number of elements
+    fn len(&self) -> usize;
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// Most links between any element and its root; O(n)
+    fn max_depth(&self) -> usize;
+
+    // Listing the components. The defaults find every element, which
+    // works for any strategy; UnionFind answers from its root sizes.
+
+    /// Every component, each in increasing order and ordered by its
+    /// smallest element
+    fn groups(&mut self) -> Vec<Vec<usize>> {
+        let mut groups: Vec<Vec<usize>> = Vec::new();
+        let mut group_of = vec![usize::MAX; self.len()]; // by representative
+        for x in 0..self.len() {
+            let r = self.find(x);
+            if group_of[r] == usize::MAX {
+                group_of[r] = groups.len();
+                groups.push(Vec::new());
+            }
+            groups[group_of[r]].push(x);
+        }
+        groups
+    }
+    /// The elements of x's component, in increasing order
+    fn members(&mut self, x: usize) -> Vec<usize> {
+        let r = self.find(x);
+        (0..self.len()).filter(|y| self.find(*y) == r).collect()
+    }
+    /// Number of elements in x's component
+    fn component_size(&mut self, x: usize) -> usize {
+        self.members(x).len()
+    }
+    /// Representative of a largest component, None if there are no
+    /// elements
+    fn largest(&mut self) -> Option<usize> {
+        let mut best: Option<Vec<usize>> = None;
+        for group in self.groups() {
+            if best.as_ref().is_none_or(|b| b.len() < group.len()) {
+                best = Some(group);
+            }
+        }
+        best.map(|group| self.find(group[0]))
+    }
+    /// (size, how many components have it), by increasing size
+    fn size_histogram(&mut self) -> Vec<(usize, usize)> {
+        let sizes: Vec<usize> = self.groups().iter().map(|g| g.len()).collect();
+        histogram(sizes)
+    }
+}
+
+/// Most links between any of 0..n and its root, where parent(x) is x
+/// for roots. Each depth is worked out once, so O(n) even for chains.
+fn max_depth<F: Fn(usize) -> usize>(n: usize, parent: F) -> usize {
+    const UNKNOWN: usize = usize::MAX;
+    let mut depth = vec![UNKNOWN; n];
+    let mut path = Vec::new();
+    let mut max = 0;
+    for x in 0..n {
+        let mut y = x;
+        while depth[y] == UNKNOWN && parent(y) != y {
+            path.push(y);
+            y = parent(y);
+        }
+        if depth[y] == UNKNOWN {
+            depth[y] = 0; // a root
+        }
+        let mut d = depth[y];
+        while let Some(z) = path.pop() {
+            d += 1;
+            depth[z] = d;
+        }
+        max = max.max(depth[x]);
+    }
+    max
+}
+
+fn node_parent(sets: &[Node], x: usize) -> usize {
+    match sets[x] {
+        Node::Root(_) => x,
+        Node::Leaf(p) => p,
+    }
+}
+
+/// What the finds have done, kept with the "stats" feature to check
+/// the balancing claims on real workloads. Without the feature none
+/// of this is compiled in.
+#[cfg(feature = "stats")]
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct Stats {
+    pub finds: u64,        // roots looked up, two per union
+    pub total_path: u64,   // links walked up to them
+    pub max_path: usize,   // most links walked by one find
+    pub compressions: u64, // links moved closer to their root
+    pub max_depth: usize,  // deepest element now, filled in by stats()
+}
+
+#[cfg(feature = "stats")]
+impl Stats {
+    fn record(&mut self, path: usize, compressions: usize) {
+        self.finds += 1;
+        self.total_path += path as u64;
+        self.max_path = self.max_path.max(path);
+        self.compressions += compressions as u64;
+    }
+}
+
+/// Unions of equal sized trees, each round doubling them, so every
+/// linking policy makes trees lg(n) deep (a binomial tree): the worst
+/// case for the balanced strategies, used by the tests and the bench
+#[allow(dead_code)]
+pub fn binomial(n: usize) -> Vec<(usize, usize)> {
+    let mut unions = Vec::new();
+    let mut step = 1;
+    while step < n {
+        for i in (0..(n - step)).step_by(2 * step) {
+            unions.push((i, i + step));
+        }
+        step *= 2;
+    }
+    unions
+}
+
+/// Run lengths of the sorted sizes
+fn histogram(mut sizes: Vec<usize>) -> Vec<(usize, usize)> {
+    sizes.sort();
+    let mut out: Vec<(usize, usize)> = Vec::new();
+    for size in sizes {
+        match out.last_mut() {
+            Some(&mut (s, ref mut n)) if s == size => *n += 1,
+            _ => out.push((size, 1)),
+        }
+    }
+    out
+}
 
 pub mod quick_find {
     #![allow(dead_code)]
@@ -33,33 +186,67 @@ pub mod quick_find {
         sets[from] == sets[to]
     }
 
-    pub fn union(sets: &mut [usize], from: usize, to: usize) {
+    pub fn union(sets: &mut [usize], from: usize, to: usize) -> bool {
         // Joins two elements; O(n)
         let from_root = sets[from];
         let to_root = sets[to];
+        if from_root == to_root {
+            return false;
+        }
         // change all that have from_root to to_root in O(n)
-        for i in 0..sets.len() {
-            if sets[i] == from_root {
-                sets[i] = to_root;
+        for s in sets.iter_mut() {
+            if *s == from_root {
+                *s = to_root;
             }
         }
+        true
+    }
+
+    /// The sets grouped by value, each group in increasing order and
+    /// ordered by its smallest element; O(n)
+    pub fn groups(sets: &[usize]) -> Vec<Vec<usize>> {
+        let mut idxs: Vec<Vec<usize>> = vec![Vec::new(); sets.len()];
+        for (i, val) in sets.iter().enumerate() {
+            idxs[*val].push(i);
+        }
+        let mut out: Vec<Vec<usize>> = idxs.into_iter().filter(|xs| !xs.is_empty()).collect();
+        out.sort_by_key(|xs| xs[0]);
+        out
     }
 
-    // WIP
-    // pub fn show_sets(sets: &[usize]) {
-    //     // Prints the grouped sets
-    //     let mut idxs: Vec<Vec<usize>> = Vec::new();
-    //     for i in 0..sets.len() {
-    //         idxs.push(Vec::new());
-    //     }
-    //     for (i,val) in sets.iter().enumerate() {
-    //         match idxs.get(*val) {
-    //             Some(ref xs) => (*xs).push(i),
-    //             None => (),
-    //         }
-    //     }
-    //     println!("{:?}", idxs);
-    // }
+    pub struct QuickFind {
+        sets: Vec<usize>,
+        count: usize,
+    }
+
+    impl super::DisjointSet for QuickFind {
+        fn new(n: usize) -> Self {
+            QuickFind { sets: (0..n).collect(), count: n }
+        }
+        fn union(&mut self, a: usize, b: usize) -> bool {
+            let merged = union(&mut self.sets, a, b);
+            self.count -= merged as usize;
+            merged
+        }
+        fn find(&mut self, x: usize) -> usize {
+            self.sets[x]
+        }
+        fn connected(&mut self, a: usize, b: usize) -> bool {
+            connected(&self.sets, a, b)
+        }
+        fn count(&self) -> usize {
+            self.count
+        }
+        fn len(&self) -> usize {
+            self.sets.len()
+        }
+        fn max_depth(&self) -> usize {
+            super::max_depth(self.sets.len(), |x| self.sets[x])
+        }
+        fn groups(&mut self) -> Vec<Vec<usize>> {
+            groups(&self.sets)
+        }
+    }
 }
 
 pub mod quick_union {
@@ -72,19 +259,49 @@ pub mod quick_union {
     // Since root is O(n), everything else is O(n)
     fn root(sets: & [usize], node: usize) -> usize {
         // Check the root by traversing the tree.
-        // The longest possible tree is N -> O(n)
-        if sets[node] != node {
-            root(sets, sets[node])
-        } else {
-            node
+        // The longest possible tree is N -> O(n), too deep to recurse
+        let mut node = node;
+        while sets[node] != node {
+            node = sets[node];
         }
+        node
     }
-    pub fn union(sets: &mut [usize], from: usize, to: usize) {
-        sets[root(sets,from)] = root(sets,to);
+    pub fn union(sets: &mut [usize], from: usize, to: usize) -> bool {
+        let (from_root, to_root) = (root(sets, from), root(sets, to));
+        sets[from_root] = to_root;
+        from_root != to_root
     }
     pub fn connected(sets: &[usize], from: usize, to: usize) -> bool {
         root(sets,from) == root(sets,to)
     }
+
+    pub struct QuickUnion {
+        sets: Vec<usize>,
+        count: usize,
+    }
+
+    impl super::DisjointSet for QuickUnion {
+        fn new(n: usize) -> Self {
+            QuickUnion { sets: (0..n).collect(), count: n }
+        }
+        fn union(&mut self, a: usize, b: usize) -> bool {
+            let merged = union(&mut self.sets, a, b);
+            self.count -= merged as usize;
+            merged
+        }
+        fn find(&mut self, x: usize) -> usize {
+            root(&self.sets, x)
+        }
+        fn count(&self) -> usize {
+            self.count
+        }
+        fn len(&self) -> usize {
+            self.sets.len()
+        }
+        fn max_depth(&self) -> usize {
+            super::max_depth(self.sets.len(), |x| self.sets[x])
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -93,6 +310,28 @@ pub enum Node {
     Leaf(usize), // Leafs point to some other Node
 }
 
+/// Joins the trees of two roots, each with its depth, the way both
+/// b_quick_union and pc_quick_union do: the shallow one goes under
+/// the deep one. Returns false if they are the same root.
+fn link(sets: &mut [Node], (from_root, from_depth): (usize, u32),
+        (to_root, to_depth): (usize, u32)) -> bool {
+    // guard agains infinite loop (& extra work)
+    if from_root == to_root {
+        return false;
+    }
+    // Add the shallow one to the deeper one
+    if from_depth < to_depth {
+        sets[from_root] = Node::Leaf(to_root);
+    } else if from_depth == to_depth {
+        // if equal, increase the weight
+        sets[to_root] = Node::Root(to_depth + 1);
+        sets[from_root] = Node::Leaf(to_root);
+    } else {
+        sets[to_root] = Node::Leaf(from_root);
+    }
+    true
+}
+
 pub mod b_quick_union {
     #![allow(dead_code)]
     /* To prevent the trees from growing deep, we *balance* them:
@@ -103,38 +342,199 @@ pub mod b_quick_union {
      */
     use super::Node;
     fn root(sets: &[Node], n: usize) -> (usize, u32) {
+        walk(sets, n, || ())
+    }
+    /// Like root, calling step for every link it follows
+    fn walk<F: FnMut()>(sets: &[Node], n: usize, mut step: F) -> (usize, u32) {
         // O(lg(n)) since the trees are weighted
-        match sets[n] {
-            Node::Root(x) => (n, x),
-            Node::Leaf(r) => root(sets, r),
+        let mut n = n;
+        loop {
+            match sets[n] {
+                Node::Root(x) => return (n, x),
+                Node::Leaf(r) => {
+                    step();
+                    n = r;
+                },
+            }
         }
     }
-    fn set_leaf(sets: &mut [Node], n: usize, r: usize) {
-        // Used to impose n != r preconditions
-        assert!(n != r);
-        sets[n] = Node::Leaf(r);
+    pub fn union(sets: &mut [Node], from: usize, to: usize) -> bool {
+        // O(lg(n)) since it depends on root
+        let from = root(sets, from);
+        let to = root(sets, to);
+        super::link(sets, from, to)
     }
-    pub fn union(sets: &mut [Node], from: usize, to: usize) {
+    pub fn connected(sets: &[Node], from: usize, to: usize) -> bool {
         // O(lg(n)) since it depends on root
-        let (from_root, from_depth) = root(sets, from);
-        let (to_root, to_depth) = root(sets, to);
-        // guard agains infinite loop (& extra work)
-        if from_root != to_root {
-            // Add the shallow one to the deeper one
-            if from_depth < to_depth {
-                set_leaf(sets, from_root, to_root);
-            } else if from_depth == to_depth {
-                // if equal, increase the weight
-                sets[to_root] = Node::Root(to_depth + 1);
-                set_leaf(sets, from_root, to_root);
+        root(sets,from) == root(sets,to)
+    }
+
+    pub struct BalancedQuickUnion {
+        sets: Vec<Node>,
+        count: usize,
+        #[cfg(feature = "stats")]
+        stats: super::Stats,
+    }
+
+    #[cfg(feature = "stats")]
+    impl BalancedQuickUnion {
+        pub fn stats(&self) -> super::Stats {
+            use super::DisjointSet;
+            super::Stats { max_depth: self.max_depth(), ..self.stats }
+        }
+        pub fn reset_stats(&mut self) {
+            self.stats = super::Stats::default();
+        }
+    }
+
+    impl BalancedQuickUnion {
+        fn root(&mut self, x: usize) -> (usize, u32) {
+            #[cfg(feature = "stats")]
+            let mut path = 0;
+            let r = walk(&self.sets, x, || {
+                #[cfg(feature = "stats")]
+                {
+                    path += 1;
+                }
+            });
+            #[cfg(feature = "stats")]
+            self.stats.record(path, 0);
+            r
+        }
+    }
+
+    impl super::DisjointSet for BalancedQuickUnion {
+        fn new(n: usize) -> Self {
+            BalancedQuickUnion {
+                sets: vec![Node::Root(1); n],
+                count: n,
+                #[cfg(feature = "stats")]
+                stats: super::Stats::default(),
+            }
+        }
+        fn union(&mut self, a: usize, b: usize) -> bool {
+            let (a, b) = (self.root(a), self.root(b));
+            let merged = super::link(&mut self.sets, a, b);
+            self.count -= merged as usize;
+            merged
+        }
+        fn find(&mut self, x: usize) -> usize {
+            self.root(x).0
+        }
+        fn count(&self) -> usize {
+            self.count
+        }
+        fn len(&self) -> usize {
+            self.sets.len()
+        }
+        fn max_depth(&self) -> usize {
+            super::max_depth(self.sets.len(), |x| super::node_parent(&self.sets, x))
+        }
+    }
+
+    /* Without path compression a union only ever changes the two
+     * roots it joins, so it can be undone by putting them back. A
+     * history of those old roots lets backtracking searches take
+     * unions back, newest first, in O(1) each.
+     * Links are by rank, like BalancedQuickUnion: Node::Root holds
+     * each tree's height, which is exactly its rank since nothing
+     * here compresses paths.
+     */
+
+    /// How far back to roll, from RollbackUnionFind::snapshot: the
+    /// history's length then, and the generation of its last union so
+    /// a history rolled back and grown again is not mistaken for it
+    #[derive(Debug, Copy, Clone, PartialEq)]
+    pub struct Snapshot {
+        len: usize,
+        generation: usize,
+    }
+
+    pub struct RollbackUnionFind {
+        sets: Vec<Node>,
+        // each union's generation and the roots it changed
+        history: Vec<(usize, [(usize, Node); 2])>,
+        generations: usize, // unions ever made, numbering them from 1
+    }
+
+    impl RollbackUnionFind {
+        pub fn new(n: usize) -> Self {
+            RollbackUnionFind { sets: vec![Node::Root(1); n], history: Vec::new(), generations: 0 }
+        }
+        pub fn len(&self) -> usize {
+            self.sets.len()
+        }
+        pub fn is_empty(&self) -> bool {
+            self.sets.is_empty()
+        }
+        /// Number of components: every union in the history joined two
+        pub fn count(&self) -> usize {
+            self.sets.len() - self.history.len()
+        }
+        pub fn find(&self, x: usize) -> usize {
+            root(&self.sets, x).0
+        }
+        pub fn connected(&self, a: usize, b: usize) -> bool {
+            connected(&self.sets, a, b)
+        }
+        /// Joins the components of a and b, returning false (and
+        /// recording nothing) if they were already the same one
+        pub fn union(&mut self, a: usize, b: usize) -> bool {
+            let (a, b) = (self.find(a), self.find(b));
+            if a == b {
+                return false;
+            }
+            self.generations += 1;
+            self.history.push((self.generations, [(a, self.sets[a]), (b, self.sets[b])]));
+            union(&mut self.sets, a, b);
+            true
+        }
+        /// Generation of the last of the first len unions, 0 if len is
+        /// 0 and None if the history is shorter
+        fn generation_at(&self, len: usize) -> Option<usize> {
+            if len == 0 {
+                Some(0)
             } else {
-                set_leaf(sets, to_root, from_root);
+                self.history.get(len - 1).map(|&(generation, _)| generation)
+            }
+        }
+        pub fn snapshot(&self) -> Snapshot {
+            let len = self.history.len();
+            Snapshot { len, generation: self.generation_at(len).unwrap_or(0) }
+        }
+        /// Undoes every union since snapshot was taken. Snapshots
+        /// taken after it are no longer valid, and rolling back to
+        /// one panics.
+        pub fn rollback_to(&mut self, snapshot: Snapshot) {
+            assert!(self.generation_at(snapshot.len) == Some(snapshot.generation),
+                    "snapshot was already rolled back");
+            for (_, old_roots) in self.history.drain(snapshot.len..).rev() {
+                for &(x, node) in old_roots.iter() {
+                    self.sets[x] = node;
+                }
             }
         }
     }
-    pub fn connected(sets: &[Node], from: usize, to: usize) -> bool {
-        // O(lg(n)) since it depends on root
-        root(sets,from) == root(sets,to)
+
+    impl super::DisjointSet for RollbackUnionFind {
+        fn new(n: usize) -> Self {
+            RollbackUnionFind::new(n)
+        }
+        fn union(&mut self, a: usize, b: usize) -> bool {
+            RollbackUnionFind::union(self, a, b)
+        }
+        fn find(&mut self, x: usize) -> usize {
+            RollbackUnionFind::find(self, x)
+        }
+        fn count(&self) -> usize {
+            RollbackUnionFind::count(self)
+        }
+        fn len(&self) -> usize {
+            RollbackUnionFind::len(self)
+        }
+        fn max_depth(&self) -> usize {
+            super::max_depth(self.sets.len(), |x| super::node_parent(&self.sets, x))
+        }
     }
 }
 
@@ -145,6 +545,8 @@ pub mod pc_quick_union {
      * ourselves from future work.
      */
 
+    use std::fmt;
+
     use super::Node;
     fn set_leaf(sets: &mut [Node], n: usize, r: usize) {
         // Used to impose n != r preconditions
@@ -152,98 +554,1271 @@ pub mod pc_quick_union {
         sets[n] = Node::Leaf(r);
     }
     fn root(sets: &mut [Node], n: usize) -> (usize, u32) {
+        walk(sets, n, |_| ())
+    }
+    /// Like root, calling step for every link on the path with
+    /// whether compressing it moved it
+    fn walk<F: FnMut(bool)>(sets: &mut [Node], n: usize, mut step: F) -> (usize, u32) {
         // O(lg(n)) since the trees are weighted
-        // The function is can not be Tail Call Optimized
-        match sets[n] {
-            Node::Root(x) => (n, x),
-            Node::Leaf(r) => {
-                let (r, d) = root(sets, r);
-                set_leaf(sets, n, r); // Compress the path
-                (r, d)
+        // Recursing to compress on the way back could not be Tail
+        // Call Optimized, so walk up twice instead
+        let mut r = n;
+        let depth = loop {
+            match sets[r] {
+                Node::Root(d) => break d,
+                Node::Leaf(p) => r = p,
             }
+        };
+        let mut x = n;
+        while let Node::Leaf(p) = sets[x] {
+            step(p != r);
+            set_leaf(sets, x, r); // Compress the path
+            x = p;
         }
+        (r, depth)
     }
-    pub fn union(sets: &mut [Node], from: usize, to: usize) {
+    pub fn union(sets: &mut [Node], from: usize, to: usize) -> bool {
         // O(lg(n)) since it depends on root
-        let (from_root, from_depth) = root(sets, from);
-        let (to_root, to_depth) = root(sets, to);
-        // guard agains infinite loop (& extra work)
-        if from_root != to_root {
-            // Add the shallow one to the deeper one
-            if from_depth < to_depth {
-                set_leaf(sets, from_root, to_root);
-            } else if from_depth == to_depth {
-                // if equal, increase the weight
-                sets[to_root] = Node::Root(to_depth + 1);
-                set_leaf(sets, from_root, to_root);
-            } else {
-                set_leaf(sets, to_root, from_root);
-            }
-        }
+        let from = root(sets, from);
+        let to = root(sets, to);
+        super::link(sets, from, to)
     }
     pub fn connected(sets: &mut [Node], from: usize, to: usize) -> bool {
         // O(lg(n)) since it depends on root
         root(sets,from) == root(sets,to)
     }
+
+    pub struct CompressedQuickUnion {
+        sets: Vec<Node>,
+        count: usize,
+        #[cfg(feature = "stats")]
+        stats: super::Stats,
+    }
+
+    #[cfg(feature = "stats")]
+    impl CompressedQuickUnion {
+        pub fn stats(&self) -> super::Stats {
+            use super::DisjointSet;
+            super::Stats { max_depth: self.max_depth(), ..self.stats }
+        }
+        pub fn reset_stats(&mut self) {
+            self.stats = super::Stats::default();
+        }
+    }
+
+    impl CompressedQuickUnion {
+        fn root(&mut self, x: usize) -> (usize, u32) {
+            #[cfg(feature = "stats")]
+            let (mut path, mut moved) = (0, 0);
+            let r = walk(&mut self.sets, x, |_moved| {
+                #[cfg(feature = "stats")]
+                {
+                    path += 1;
+                    moved += _moved as usize;
+                }
+            });
+            #[cfg(feature = "stats")]
+            self.stats.record(path, moved);
+            r
+        }
+    }
+
+    impl super::DisjointSet for CompressedQuickUnion {
+        fn new(n: usize) -> Self {
+            CompressedQuickUnion {
+                sets: vec![Node::Root(1); n],
+                count: n,
+                #[cfg(feature = "stats")]
+                stats: super::Stats::default(),
+            }
+        }
+        fn union(&mut self, a: usize, b: usize) -> bool {
+            let (a, b) = (self.root(a), self.root(b));
+            let merged = super::link(&mut self.sets, a, b);
+            self.count -= merged as usize;
+            merged
+        }
+        fn find(&mut self, x: usize) -> usize {
+            self.root(x).0
+        }
+        fn count(&self) -> usize {
+            self.count
+        }
+        fn len(&self) -> usize {
+            self.sets.len()
+        }
+        fn max_depth(&self) -> usize {
+            super::max_depth(self.sets.len(), |x| super::node_parent(&self.sets, x))
+        }
+    }
+
+    /* The same compressed trees can also answer *how* two elements
+     * are related: each leaf keeps its value relative to its parent
+     * (its potential), and compressing a path adds the potentials
+     * along it so the leaf then holds its value relative to the root.
+     * Then value(a) - value(b) is a's potential minus b's, whenever
+     * they share a root.
+     */
+
+    /// Potentials: a commutative group, written additively
+    pub trait Group: Copy + PartialEq + fmt::Debug {
+        fn zero() -> Self;
+        fn add(self, other: Self) -> Self;
+        fn neg(self) -> Self;
+        fn sub(self, other: Self) -> Self {
+            self.add(other.neg())
+        }
+    }
+
+    /// Offsets, as in relative positions
+    impl Group for i64 {
+        fn zero() -> Self {
+            0
+        }
+        fn add(self, other: Self) -> Self {
+            self + other
+        }
+        fn neg(self) -> Self {
+            -self
+        }
+    }
+
+    /// Parity under XOR: true when two elements are on different sides
+    impl Group for bool {
+        fn zero() -> Self {
+            false
+        }
+        fn add(self, other: Self) -> Self {
+            self ^ other
+        }
+        fn neg(self) -> Self {
+            self
+        }
+    }
+
+    /// A constraint that disagrees with the ones already added
+    #[derive(Debug, PartialEq)]
+    pub struct Contradiction<G> {
+        pub known: G, // value(a) - value(b) implied so far
+        pub given: G, // what the rejected constraint asked for
+    }
+
+    impl<G: fmt::Debug> fmt::Display for Contradiction<G> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "difference is already {:?}, not {:?}", self.known, self.given)
+        }
+    }
+
+    pub struct PotentialUnionFind<G: Group> {
+        parent: Vec<usize>, // roots point to themselves
+        potential: Vec<G>,  // value(x) - value(parent[x])
+        size: Vec<usize>,   // only meaningful at roots
+        count: usize,
+    }
+
+    impl<G: Group> PotentialUnionFind<G> {
+        pub fn new(n: usize) -> Self {
+            PotentialUnionFind {
+                parent: (0..n).collect(),
+                potential: vec![G::zero(); n],
+                size: vec![1; n],
+                count: n,
+            }
+        }
+        pub fn len(&self) -> usize {
+            self.parent.len()
+        }
+        pub fn is_empty(&self) -> bool {
+            self.parent.is_empty()
+        }
+        pub fn count(&self) -> usize {
+            self.count
+        }
+        /// Root of x's component and value(x) - value(root),
+        /// compressing the path
+        pub fn find(&mut self, x: usize) -> (usize, G) {
+            // First pass: the root, and x's value relative to it
+            let mut r = x;
+            let mut total = G::zero();
+            while self.parent[r] != r {
+                total = total.add(self.potential[r]);
+                r = self.parent[r];
+            }
+            // Second pass: each node on the path is worth what is left
+            // of the total once the links below it are taken off
+            let mut y = x;
+            let mut left = total;
+            while y != r {
+                let next = self.parent[y];
+                let old = self.potential[y];
+                self.potential[y] = left;
+                self.parent[y] = r;
+                left = left.sub(old);
+                y = next;
+            }
+            (r, total)
+        }
+        pub fn connected(&mut self, a: usize, b: usize) -> bool {
+            self.find(a).0 == self.find(b).0
+        }
+        /// value(a) - value(b), if the constraints so far imply it
+        pub fn diff(&mut self, a: usize, b: usize) -> Option<G> {
+            let (ra, pa) = self.find(a);
+            let (rb, pb) = self.find(b);
+            if ra == rb {
+                Some(pa.sub(pb))
+            } else {
+                None
+            }
+        }
+        /// Adds the constraint value(a) - value(b) = d. Returns
+        /// whether it joined two components, or the contradiction if
+        /// a and b are already related some other way (and then
+        /// changes nothing).
+        pub fn union(&mut self, a: usize, b: usize, d: G) -> Result<bool, Contradiction<G>> {
+            let (ra, pa) = self.find(a);
+            let (rb, pb) = self.find(b);
+            if ra == rb {
+                let known = pa.sub(pb);
+                return if known == d {
+                    Ok(false)
+                } else {
+                    Err(Contradiction { known, given: d })
+                };
+            }
+            // value(ra) - value(rb)
+            //   = (value(a) - pa) - (value(b) - pb) = d + pb - pa
+            let ra_over_rb = d.add(pb).sub(pa);
+            if self.size[ra] < self.size[rb] {
+                self.parent[ra] = rb;
+                self.potential[ra] = ra_over_rb;
+                self.size[rb] += self.size[ra];
+            } else {
+                self.parent[rb] = ra;
+                self.potential[rb] = ra_over_rb.neg();
+                self.size[ra] += self.size[rb];
+            }
+            self.count -= 1;
+            Ok(true)
+        }
+    }
 }
 
-pub mod test {
-    // Test Helpers
-    fn random_upto(n: usize) -> usize {
-        use super::rand::random;
-        random::<usize>() % n
+pub mod concurrent {
+    #![allow(dead_code)]
+    /* The other strategies need &mut to change their sets, so only
+     * one thread can use them at a time. Here every parent pointer is
+     * an atomic word: a union links one root under another with a
+     * compare and swap, retrying if another thread linked it first,
+     * and finds halve their paths with compare and swaps that are
+     * allowed to fail, since another thread only ever moves a
+     * pointer closer to the root.
+     * Roots are linked by index (the lower one goes under the higher
+     * one), which keeps the trees acyclic without a rank to update
+     * alongside the parent; path halving keeps them shallow.
+     */
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    pub struct ConcurrentUnionFind {
+        parent: Vec<AtomicUsize>, // roots point to themselves
+        count: AtomicUsize,
     }
-    fn start_sets(sets: &mut [usize]) {
-        for i in 0..sets.len() {
-            sets[i] = i;
+
+    impl ConcurrentUnionFind {
+        pub fn new(n: usize) -> Self {
+            ConcurrentUnionFind {
+                parent: (0..n).map(AtomicUsize::new).collect(),
+                count: AtomicUsize::new(n),
+            }
+        }
+        pub fn len(&self) -> usize {
+            self.parent.len()
+        }
+        pub fn is_empty(&self) -> bool {
+            self.parent.is_empty()
+        }
+        /// Number of components once the unions in flight are done
+        pub fn count(&self) -> usize {
+            self.count.load(Ordering::Acquire)
+        }
+        /// Root of x's component when the walk reached it; it may
+        /// have been linked under another since
+        pub fn find(&self, x: usize) -> usize {
+            let mut x = x;
+            loop {
+                let p = self.parent[x].load(Ordering::Acquire);
+                if p == x {
+                    return x;
+                }
+                let gp = self.parent[p].load(Ordering::Acquire);
+                if gp != p {
+                    // Halve the path; losing the race is fine
+                    let _ = self.parent[x].compare_exchange_weak(p, gp, Ordering::AcqRel,
+                                                                 Ordering::Relaxed);
+                }
+                x = gp;
+            }
+        }
+        /// Joins the components of a and b, returning false if they
+        /// were already the same one
+        pub fn union(&self, a: usize, b: usize) -> bool {
+            loop {
+                let (ra, rb) = (self.find(a), self.find(b));
+                if ra == rb {
+                    return false;
+                }
+                let (low, high) = if ra < rb { (ra, rb) } else { (rb, ra) };
+                // Only succeeds if low is still a root
+                if self.parent[low].compare_exchange(low, high, Ordering::AcqRel,
+                                                     Ordering::Acquire).is_ok() {
+                    self.count.fetch_sub(1, Ordering::AcqRel);
+                    return true;
+                }
+            }
+        }
+        pub fn connected(&self, a: usize, b: usize) -> bool {
+            loop {
+                let (ra, rb) = (self.find(a), self.find(b));
+                if ra == rb {
+                    return true;
+                }
+                // Different roots only mean different components if
+                // ra was still a root after rb was found
+                if self.parent[ra].load(Ordering::Acquire) == ra {
+                    return false;
+                }
+            }
+        }
+    }
+
+    impl super::DisjointSet for ConcurrentUnionFind {
+        fn new(n: usize) -> Self {
+            ConcurrentUnionFind::new(n)
+        }
+        fn union(&mut self, a: usize, b: usize) -> bool {
+            ConcurrentUnionFind::union(self, a, b)
+        }
+        fn find(&mut self, x: usize) -> usize {
+            ConcurrentUnionFind::find(self, x)
+        }
+        fn connected(&mut self, a: usize, b: usize) -> bool {
+            ConcurrentUnionFind::connected(self, a, b)
+        }
+        fn count(&self) -> usize {
+            ConcurrentUnionFind::count(self)
+        }
+        fn len(&self) -> usize {
+            ConcurrentUnionFind::len(self)
+        }
+        fn max_depth(&self) -> usize {
+            super::max_depth(self.len(), |x| self.parent[x].load(Ordering::Acquire))
         }
     }
-    // Main tests
-    pub fn main() {
-        use super::*;
+}
+
+/// Which root goes under the other in a union
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Link {
+    BySize, // the smaller component's root
+    ByRank, // the root with the lower rank, an upper bound on depth
+}
+
+/// How a find shortens the path it walked
+#[allow(dead_code)] // only the tests pick the one-pass kinds so far
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Compress {
+    Full,      // every node on the path points at the root, two passes
+    Halving,   // every other node points at its grandparent, one pass
+    Splitting, // every node points at its grandparent, one pass
+}
 
+/// Disjoint sets over 0..count, growable at runtime
+/// Weighted quick union with path compression, as in pc_quick_union,
+/// but weighted by size by default so each root also knows how big
+/// its component is. Finds are loops, so no path is too long.
+pub struct UnionFind {
+    parent: Vec<usize>, // roots point to themselves
+    size: Vec<usize>,   // only meaningful at roots
+    rank: Vec<u8>,      // only meaningful at roots, and only with Link::ByRank
+    next: Vec<usize>,   // each component's elements as a circular list
+    count: usize,       // number of components
+    largest: usize,     // root of a largest component, if any
+    link: Link,
+    compress: Compress,
+    #[cfg(feature = "stats")]
+    stats: Stats,
+}
+
+impl UnionFind {
+    /// n elements, each in its own component
+    pub fn new(n: usize) -> Self {
+        UnionFind::with_policies(n, Link::BySize, Compress::Full)
+    }
+    pub fn with_policies(n: usize, link: Link, compress: Compress) -> Self {
+        UnionFind {
+            parent: (0..n).collect(),
+            size: vec![1; n],
+            rank: vec![0; n],
+            next: (0..n).collect(),
+            count: n,
+            largest: 0,
+            link,
+            compress,
+            #[cfg(feature = "stats")]
+            stats: Stats::default(),
+        }
+    }
+    /// Adds a new element in its own component and returns it
+    pub fn add(&mut self) -> usize {
+        let x = self.parent.len();
+        self.parent.push(x);
+        self.size.push(1);
+        self.rank.push(0);
+        self.next.push(x);
+        self.count += 1;
+        x
+    }
+    /// Number of elements
+    pub fn len(&self) -> usize {
+        self.parent.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.parent.is_empty()
+    }
+    /// Number of components
+    pub fn count(&self) -> usize {
+        self.count
+    }
+    /// Root of x's component, compressing the path to it
+    pub fn find(&mut self, x: usize) -> usize {
+        #[cfg(feature = "stats")]
+        let (mut path, mut moved) = (0, 0);
+        let parent = &mut self.parent;
+        let mut node = x;
+        match self.compress {
+            Compress::Full => {
+                let mut root = x;
+                while parent[root] != root {
+                    #[cfg(feature = "stats")]
+                    {
+                        path += 1;
+                    }
+                    root = parent[root];
+                }
+                // Second pass: point everything on the path at the root
+                while node != root {
+                    let next = parent[node];
+                    #[cfg(feature = "stats")]
+                    {
+                        moved += (next != root) as usize;
+                    }
+                    parent[node] = root;
+                    node = next;
+                }
+            },
+            Compress::Halving => {
+                while parent[node] != node {
+                    #[cfg(feature = "stats")]
+                    {
+                        // node jumps two links unless its parent is the root
+                        let skips = parent[parent[node]] != parent[node];
+                        path += 1 + skips as usize;
+                        moved += skips as usize;
+                    }
+                    parent[node] = parent[parent[node]];
+                    node = parent[node];
+                }
+            },
+            Compress::Splitting => {
+                while parent[node] != node {
+                    let next = parent[node];
+                    #[cfg(feature = "stats")]
+                    {
+                        path += 1;
+                        moved += (parent[next] != next) as usize;
+                    }
+                    parent[node] = parent[next];
+                    node = next;
+                }
+            },
+        }
+        #[cfg(feature = "stats")]
+        self.stats.record(path, moved);
+        node
+    }
+    pub fn connected(&mut self, a: usize, b: usize) -> bool {
+        self.find(a) == self.find(b)
+    }
+    /// Joins the components of a and b, returning false if they
+    /// were already the same one
+    pub fn union(&mut self, a: usize, b: usize) -> bool {
+        let (a, b) = (self.find(a), self.find(b));
+        if a == b {
+            return false;
+        }
+        // Add the small one to the big one
+        let a_is_smaller = match self.link {
+            Link::BySize => self.size[a] < self.size[b],
+            Link::ByRank => self.rank[a] < self.rank[b],
+        };
+        let (small, big) = if a_is_smaller { (a, b) } else { (b, a) };
+        if self.link == Link::ByRank && self.rank[small] == self.rank[big] {
+            self.rank[big] += 1;
+        }
+        self.parent[small] = big;
+        self.size[big] += self.size[small];
+        // Swapping where two circles go next splices them into one
+        self.next.swap(small, big);
+        self.count -= 1;
+        // Components only grow, so the largest is always the last
+        // one to outgrow it
+        if self.size[big] > self.size[self.largest] || small == self.largest {
+            self.largest = big;
+        }
+        true
+    }
+    /// Number of elements in x's component
+    pub fn component_size(&mut self, x: usize) -> usize {
+        let root = self.find(x);
+        self.size[root]
+    }
+    /// Every component, each in increasing order and ordered by its
+    /// smallest element; O(n)
+    pub fn groups(&mut self) -> Vec<Vec<usize>> {
+        // The root sizes say how much room each group needs
+        let mut groups: Vec<Vec<usize>> = Vec::with_capacity(self.count);
+        let mut group_of = vec![usize::MAX; self.len()]; // by root
+        for x in 0..self.len() {
+            let r = self.find(x);
+            if group_of[r] == usize::MAX {
+                group_of[r] = groups.len();
+                groups.push(Vec::with_capacity(self.size[r]));
+            }
+            groups[group_of[r]].push(x);
+        }
+        groups
+    }
+    /// The elements of x's component, in increasing order. Goes
+    /// round its circular list, so O(s lg s) for a component of s.
+    pub fn members(&mut self, x: usize) -> Vec<usize> {
+        let r = self.find(x);
+        let mut out = Vec::with_capacity(self.size[r]);
+        let mut y = x;
+        loop {
+            out.push(y);
+            y = self.next[y];
+            if y == x {
+                break;
+            }
+        }
+        out.sort();
+        out
+    }
+    #[cfg(feature = "stats")]
+    #[allow(dead_code)] // for callers measuring their workloads
+    pub fn stats(&self) -> Stats {
+        Stats { max_depth: DisjointSet::max_depth(self), ..self.stats }
+    }
+    #[cfg(feature = "stats")]
+    #[allow(dead_code)] // for callers measuring their workloads
+    pub fn reset_stats(&mut self) {
+        self.stats = Stats::default();
+    }
+    /// Root of a largest component, None if there are no elements; O(1)
+    #[allow(dead_code)]
+    pub fn largest(&self) -> Option<usize> {
+        if self.is_empty() {
+            None
+        } else {
+            Some(self.largest)
+        }
+    }
+    /// (size, how many components have it), by increasing size.
+    /// Reads the sizes off the roots without any finds.
+    pub fn size_histogram(&self) -> Vec<(usize, usize)> {
+        let roots = (0..self.len()).filter(|x| self.parent[*x] == *x);
+        histogram(roots.map(|r| self.size[r]).collect())
+    }
+}
+
+impl DisjointSet for UnionFind {
+    fn new(n: usize) -> Self {
+        UnionFind::new(n)
+    }
+    fn union(&mut self, a: usize, b: usize) -> bool {
+        UnionFind::union(self, a, b)
+    }
+    fn find(&mut self, x: usize) -> usize {
+        UnionFind::find(self, x)
+    }
+    fn count(&self) -> usize {
+        UnionFind::count(self)
+    }
+    fn len(&self) -> usize {
+        UnionFind::len(self)
+    }
+    fn max_depth(&self) -> usize {
+        max_depth(self.len(), |x| self.parent[x])
+    }
+    fn groups(&mut self) -> Vec<Vec<usize>> {
+        UnionFind::groups(self)
+    }
+    fn members(&mut self, x: usize) -> Vec<usize> {
+        UnionFind::members(self, x)
+    }
+    fn component_size(&mut self, x: usize) -> usize {
+        UnionFind::component_size(self, x)
+    }
+    fn largest(&mut self) -> Option<usize> {
+        UnionFind::largest(self)
+    }
+    fn size_histogram(&mut self) -> Vec<(usize, usize)> {
+        UnionFind::size_histogram(self)
+    }
+}
+
+/// UnionFind over any hashable keys instead of 0..n
+/// Each key gets the next index the first time it is seen, so the
+/// keys are stored twice (as map keys and by index) and must be Clone.
+pub struct KeyedUnionFind<T: Hash + Eq> {
+    sets: UnionFind,
+    index: HashMap<T, usize>,
+    keys: Vec<T>, // keys[i] has index i
+}
+
+#[allow(dead_code)] // a set-like API, not all of it used by main yet
+impl<T: Hash + Eq + Clone> KeyedUnionFind<T> {
+    pub fn new() -> Self {
+        KeyedUnionFind { sets: UnionFind::new(0), index: HashMap::new(), keys: Vec::new() }
+    }
+    /// Index of key, adding it in its own component if it is new
+    fn intern(&mut self, key: T) -> usize {
+        if let Some(&i) = self.index.get(&key) {
+            return i;
+        }
+        let i = self.sets.add();
+        self.index.insert(key.clone(), i);
+        self.keys.push(key);
+        i
+    }
+    /// Adds key in its own component, returning false if it was
+    /// already there
+    pub fn insert(&mut self, key: T) -> bool {
+        let len = self.keys.len();
+        self.intern(key);
+        self.keys.len() > len
+    }
+    pub fn contains(&self, key: &T) -> bool {
+        self.index.contains_key(key)
+    }
+    /// Number of keys
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+    /// Number of components
+    pub fn count(&self) -> usize {
+        self.sets.count()
+    }
+    /// Joins the components of a and b, adding either if it is new.
+    /// Returns false if they were already the same one.
+    pub fn union(&mut self, a: T, b: T) -> bool {
+        let a = self.intern(a);
+        let b = self.intern(b);
+        self.sets.union(a, b)
+    }
+    /// Representative key of key's component, None for unseen keys
+    pub fn find(&mut self, key: &T) -> Option<&T> {
+        let i = *self.index.get(key)?;
+        let root = self.sets.find(i);
+        Some(&self.keys[root])
+    }
+    /// Unseen keys are only connected to themselves
+    pub fn connected(&mut self, a: &T, b: &T) -> bool {
+        match (self.index.get(a), self.index.get(b)) {
+            (Some(&a), Some(&b)) => self.sets.connected(a, b),
+            _ => a == b,
+        }
+    }
+    /// Every key in key's component, in the order they were first
+    /// seen; O(s lg s) for a component of s, like UnionFind::members
+    pub fn component(&mut self, key: &T) -> Vec<&T> {
+        let members = match self.index.get(key) {
+            Some(&i) => self.sets.members(i),
+            None => return Vec::new(),
+        };
+        let keys = &self.keys;
+        members.into_iter().map(|x| &keys[x]).collect()
+    }
+}
+
+impl<T: Hash + Eq + Clone> Default for KeyedUnionFind<T> {
+    fn default() -> Self {
+        KeyedUnionFind::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use util;
+
+    #[test]
+    fn strategies_agree() {
         const L: usize = 10;
         const UNIONS: usize = 9;
 
         let mut find_sets: [usize; L] = [0; L];
-        start_sets(&mut find_sets);
-
         let mut union_sets: [usize; L] = [0; L];
-        start_sets(&mut union_sets);
-
+        for i in 0..L {
+            find_sets[i] = i;
+            union_sets[i] = i;
+        }
         let mut balanced_sets: [Node; L] = [Node::Root(1); L];
-
         let mut compressed_sets: [Node; L] = [Node::Root(1); L];
+        let mut uf = UnionFind::new(L);
 
         let mut unions: [(usize, usize); UNIONS] = [(0,0); UNIONS];
-        for i in 0..UNIONS {
-            let from = random_upto(L);
-            let to = random_upto(L);
-            unions[i] = (from, to);
+        for u in unions.iter_mut() {
+            *u = (util::rand_upto(L), util::rand_upto(L));
         }
-        println!("The unions are: {:?}", unions);
 
         // logic for Union Find
-        for i in 0..UNIONS {
-            let (from,to) = unions[i];
+        for &(from, to) in unions.iter() {
             quick_find::union(&mut find_sets, from, to);
             quick_union::union(&mut union_sets, from, to);
             b_quick_union::union(&mut balanced_sets, from, to);
             pc_quick_union::union(&mut compressed_sets, from, to);
+            uf.union(from, to);
         }
 
-        println!("The quick_find sets are: {:?}", find_sets);
-        println!("The quick_union sets are: {:?}", union_sets);
-        println!("The balanced sets are: {:?}", balanced_sets);
-        println!("The compressed sets are: {:?}", compressed_sets);
-
-        // Test
-        for i in 0..UNIONS {
-            let (from,to) = unions[i];
+        for &(from, to) in unions.iter() {
             assert!(quick_find::connected(&find_sets, from, to));
             assert!(quick_union::connected(&union_sets, from, to));
             assert!(b_quick_union::connected(&balanced_sets, from, to));
             assert!(pc_quick_union::connected(&mut compressed_sets, from, to));
+            assert!(uf.connected(from, to));
+        }
+        for a in 0..L {
+            for b in 0..L {
+                let expected = quick_find::connected(&find_sets, a, b);
+                assert_eq!(expected, uf.connected(a, b));
+            }
+        }
+    }
+
+    // Checks a strategy against quick_find's sets, which are easy to
+    // get right
+    fn matches_quick_find<S: DisjointSet>(mut sets: S) {
+        let n = sets.len();
+        let mut expected: Vec<usize> = (0..n).collect();
+        for _ in 0..(2 * n) {
+            let (a, b) = (util::rand_upto(n), util::rand_upto(n));
+            let merged = quick_find::union(&mut expected, a, b);
+            assert_eq!(merged, sets.union(a, b));
+            let mut roots = expected.clone();
+            roots.sort();
+            roots.dedup();
+            assert_eq!(roots.len(), sets.count());
+            let x = util::rand_upto(n);
+            for y in 0..n {
+                assert_eq!(expected[x] == expected[y], sets.connected(x, y));
+                assert_eq!(expected[x] == expected[y], sets.find(x) == sets.find(y));
+            }
+            let groups = quick_find::groups(&expected);
+            assert_eq!(groups, sets.groups());
+            let members: Vec<usize> = (0..n).filter(|y| expected[*y] == expected[x]).collect();
+            assert_eq!(members.len(), sets.component_size(x));
+            assert_eq!(members, sets.members(x));
+            let most = groups.iter().map(|g| g.len()).max();
+            let largest = sets.largest().map(|r| sets.component_size(r));
+            assert_eq!(most, largest);
+            let histogram = sets.size_histogram();
+            assert_eq!(n, histogram.iter().map(|&(size, n)| size * n).sum::<usize>());
+            assert_eq!(groups.len(), histogram.iter().map(|&(_, n)| n).sum::<usize>());
+        }
+    }
+
+    const POLICIES: [(Link, Compress); 6] = [(Link::BySize, Compress::Full),
+                                             (Link::BySize, Compress::Halving),
+                                             (Link::BySize, Compress::Splitting),
+                                             (Link::ByRank, Compress::Full),
+                                             (Link::ByRank, Compress::Halving),
+                                             (Link::ByRank, Compress::Splitting)];
+
+    #[test]
+    fn every_strategy() {
+        const N: usize = 50;
+        matches_quick_find(quick_find::QuickFind::new(N));
+        matches_quick_find(quick_union::QuickUnion::new(N));
+        matches_quick_find(b_quick_union::BalancedQuickUnion::new(N));
+        matches_quick_find(pc_quick_union::CompressedQuickUnion::new(N));
+        matches_quick_find(b_quick_union::RollbackUnionFind::new(N));
+        matches_quick_find(concurrent::ConcurrentUnionFind::new(N));
+        for &(link, compress) in POLICIES.iter() {
+            matches_quick_find(UnionFind::with_policies(N, link, compress));
+        }
+    }
+
+    fn depth(uf: &UnionFind, mut x: usize) -> usize {
+        let mut d = 0;
+        while uf.parent[x] != x {
+            x = uf.parent[x];
+            d += 1;
+        }
+        d
+    }
+
+    #[test]
+    fn policies() {
+        const LG: usize = 10;
+        const N: usize = 1 << LG;
+        for &(link, compress) in POLICIES.iter() {
+            let mut uf = UnionFind::with_policies(N, link, compress);
+            for &(a, b) in binomial(N).iter() {
+                uf.union(a, b);
+            }
+            assert_eq!(1, uf.count());
+            assert_eq!(LG, (0..N).map(|x| depth(&uf, x)).max().unwrap());
+            assert_eq!(LG, uf.max_depth());
+            // One find from the deepest leaf shortens its path
+            uf.find(N - 1);
+            let after = depth(&uf, N - 1);
+            match compress {
+                Compress::Full => assert_eq!(1, after),
+                Compress::Halving | Compress::Splitting => assert_eq!(LG / 2, after),
+            }
+            assert!((0..N).all(|x| uf.find(x) == uf.find(0)));
+            assert!((0..N).all(|x| depth(&uf, x) <= 1));
+            assert_eq!(1, uf.max_depth());
+        }
+        // Ranks stay small even when sizes differ a lot
+        let mut uf = UnionFind::with_policies(3, Link::ByRank, Compress::Full);
+        uf.union(0, 1);
+        uf.union(2, 0);
+        assert_eq!(3, uf.component_size(2));
+        let root = uf.find(2);
+        assert_eq!(1, uf.rank[root]);
+    }
+
+    // Not a real test: compares the policies' speed. Run with
+    // cargo test --release policy_benchmark -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn policy_benchmark() {
+        use std::time::Instant;
+        const N: usize = 1 << 20;
+        let mut random = Vec::with_capacity(N);
+        for _ in 0..N {
+            random.push((util::rand_upto(N), util::rand_upto(N)));
+        }
+        // Find the deepest leaves first, before anything compresses
+        let finds: Vec<usize> = (0..N).rev().collect();
+        let sequences = [("random", random), ("binomial", binomial(N))];
+        println!("{:12} {:7} {:10} {:>10}", "unions", "link", "compress", "ns/op");
+        for &(name, ref unions) in sequences.iter() {
+            for &(link, compress) in POLICIES.iter() {
+                let mut uf = UnionFind::with_policies(N, link, compress);
+                let start = Instant::now();
+                for &(a, b) in unions.iter() {
+                    uf.union(a, b);
+                }
+                for x in finds.iter() {
+                    uf.find(*x);
+                }
+                let ops = unions.len() + finds.len();
+                let ns = start.elapsed().as_nanos() as f64 / ops as f64;
+                println!("{:12} {:7} {:10} {:>10.1}",
+                         name, format!("{:?}", link), format!("{:?}", compress), ns);
+            }
+        }
+    }
+
+    #[test]
+    fn union_find() {
+        let mut uf = UnionFind::new(4);
+        assert_eq!(4, uf.count());
+        assert!(uf.union(0, 1));
+        assert!(uf.union(2, 3));
+        assert!(!uf.union(1, 0));
+        assert_eq!(2, uf.count());
+        assert!(uf.connected(0, 1));
+        assert!(!uf.connected(1, 2));
+        assert_eq!(2, uf.component_size(3));
+        // grows one element at a time
+        let x = uf.add();
+        assert_eq!(4, x);
+        assert_eq!(5, uf.len());
+        assert_eq!(3, uf.count());
+        assert_eq!(1, uf.component_size(x));
+        assert!(uf.union(x, 0));
+        assert!(uf.union(3, x));
+        assert_eq!(1, uf.count());
+        assert_eq!(5, uf.component_size(2));
+        assert_eq!(uf.find(0), uf.find(4));
+
+        let mut empty = UnionFind::new(0);
+        assert!(empty.is_empty());
+        assert_eq!(0, empty.count());
+        assert_eq!(0, empty.add());
+        assert_eq!(1, empty.count());
+    }
+
+    #[test]
+    fn component_stats() {
+        let mut uf = UnionFind::new(7);
+        assert_eq!(Some(0), uf.largest());
+        uf.union(5, 3);
+        uf.union(1, 6);
+        uf.union(6, 5);
+        uf.union(0, 2);
+        assert_eq!(vec![vec![0, 2], vec![1, 3, 5, 6], vec![4]], uf.groups());
+        assert_eq!(vec![1, 3, 5, 6], uf.members(3));
+        assert_eq!(vec![4], uf.members(4));
+        assert_eq!(3, uf.count());
+        let largest = uf.largest().unwrap();
+        assert!(uf.connected(largest, 1));
+        assert_eq!(vec![(1, 1), (2, 1), (4, 1)], uf.size_histogram());
+        // the largest moves when it is overtaken
+        let more: Vec<usize> = (0..5).map(|_| uf.add()).collect();
+        for x in more.iter() {
+            uf.union(*x, 4);
+        }
+        assert!(uf.connected(uf.largest().unwrap(), 4));
+        assert_eq!(6, uf.component_size(4));
+        assert_eq!(vec![(2, 1), (4, 1), (6, 1)], uf.size_histogram());
+        assert_eq!(None, UnionFind::new(0).largest());
+        assert!(UnionFind::new(0).groups().is_empty());
+        assert_eq!(vec![vec![0, 3], vec![1, 2]], quick_find::groups(&[3, 2, 2, 3]));
+    }
+
+    #[test]
+    fn rollback() {
+        use super::b_quick_union::RollbackUnionFind;
+        let mut uf = RollbackUnionFind::new(6);
+        let empty = uf.snapshot();
+        assert!(uf.union(0, 1));
+        assert!(uf.union(2, 3));
+        let two_pairs = uf.snapshot();
+        assert!(uf.union(1, 3));
+        assert!(!uf.union(0, 2));
+        assert!(uf.union(4, 5));
+        assert_eq!(2, uf.count());
+        assert!(uf.connected(0, 3));
+        uf.rollback_to(two_pairs);
+        assert_eq!(4, uf.count());
+        assert!(!uf.connected(0, 3));
+        assert!(!uf.connected(4, 5));
+        assert!(uf.connected(2, 3));
+        // unions after a rollback can be rolled back too
+        let before = uf.snapshot();
+        assert!(uf.union(3, 4));
+        assert!(uf.connected(2, 4));
+        uf.rollback_to(before);
+        assert!(!uf.connected(2, 4));
+        uf.rollback_to(before); // nothing to undo
+        assert_eq!(4, uf.count());
+        uf.rollback_to(empty);
+        assert_eq!(6, uf.count());
+        assert!((0..6).all(|x| uf.find(x) == x));
+    }
+
+    #[test]
+    #[should_panic]
+    fn rollback_past_history() {
+        use super::b_quick_union::RollbackUnionFind;
+        let mut uf = RollbackUnionFind::new(2);
+        let start = uf.snapshot();
+        uf.union(0, 1);
+        let joined = uf.snapshot();
+        uf.rollback_to(start);
+        uf.rollback_to(joined);
+    }
+
+    #[test]
+    #[should_panic]
+    fn rollback_to_another_branch() {
+        // joined's history was replaced by one just as long
+        use super::b_quick_union::RollbackUnionFind;
+        let mut uf = RollbackUnionFind::new(4);
+        let start = uf.snapshot();
+        uf.union(0, 1);
+        let joined = uf.snapshot();
+        uf.union(2, 3);
+        uf.rollback_to(start);
+        uf.union(1, 2);
+        uf.union(0, 3);
+        uf.rollback_to(joined);
+    }
+
+    #[test]
+    fn rollback_matches_replay() {
+        use super::b_quick_union::RollbackUnionFind;
+        // Interleaves unions and rollbacks to random earlier points,
+        // checking against quick_find replaying only the unions still
+        // in effect
+        const N: usize = 30;
+        let mut uf = RollbackUnionFind::new(N);
+        let mut snapshots = vec![(uf.snapshot(), 0)];
+        let mut unions: Vec<(usize, usize)> = Vec::new();
+        for _ in 0..500 {
+            if util::rand_upto(4) == 0 {
+                let i = util::rand_upto(snapshots.len());
+                let (snapshot, len) = snapshots[i];
+                uf.rollback_to(snapshot);
+                unions.truncate(len);
+                snapshots.truncate(i + 1);
+            } else {
+                let (a, b) = (util::rand_upto(N), util::rand_upto(N));
+                uf.union(a, b);
+                unions.push((a, b));
+                if util::rand_upto(3) == 0 {
+                    snapshots.push((uf.snapshot(), unions.len()));
+                }
+            }
+            let mut expected: Vec<usize> = (0..N).collect();
+            for &(a, b) in unions.iter() {
+                quick_find::union(&mut expected, a, b);
+            }
+            assert_eq!(quick_find::groups(&expected), DisjointSet::groups(&mut uf));
+        }
+    }
+
+    #[test]
+    fn potentials() {
+        use super::pc_quick_union::{Contradiction, PotentialUnionFind};
+        // positions on a line: 1 is 3 right of 0, 2 is 5 left of 1
+        let mut line: PotentialUnionFind<i64> = PotentialUnionFind::new(4);
+        assert_eq!(Ok(true), line.union(1, 0, 3));
+        assert_eq!(Ok(true), line.union(2, 1, -5));
+        assert_eq!(Some(-2), line.diff(2, 0));
+        assert_eq!(Some(2), line.diff(0, 2));
+        assert_eq!(Some(0), line.diff(1, 1));
+        assert_eq!(None, line.diff(3, 0));
+        assert_eq!(Ok(false), line.union(0, 2, 2));
+        let wrong = line.union(0, 2, 7);
+        assert_eq!(Err(Contradiction { known: 2, given: 7 }), wrong);
+        assert_eq!("difference is already 2, not 7", wrong.unwrap_err().to_string());
+        assert_eq!(2, line.count());
+        assert_eq!(Some(-2), line.diff(2, 0));
+
+        // parity: a graph is bipartite until an odd cycle shows up
+        let mut sides: PotentialUnionFind<bool> = PotentialUnionFind::new(4);
+        for &(a, b) in [(0, 1), (1, 2), (2, 3), (3, 0)].iter() {
+            assert!(sides.union(a, b, true).is_ok());
+        }
+        assert_eq!(Some(false), sides.diff(0, 2));
+        assert_eq!(Err(Contradiction { known: false, given: true }), sides.union(0, 2, true));
+    }
+
+    #[test]
+    fn potentials_match_values() {
+        use super::pc_quick_union::PotentialUnionFind;
+        const N: usize = 200;
+        let values: Vec<i64> = (0..N).map(|_| util::rand_upto(1000) as i64 - 500).collect();
+        let mut uf: PotentialUnionFind<i64> = PotentialUnionFind::new(N);
+        let mut expected: Vec<usize> = (0..N).collect();
+        for _ in 0..(2 * N) {
+            let (a, b) = (util::rand_upto(N), util::rand_upto(N));
+            let d = values[a] - values[b];
+            let merged = !quick_find::connected(&expected, a, b);
+            quick_find::union(&mut expected, a, b);
+            // constraints true of the hidden values always fit
+            assert_eq!(Ok(merged), uf.union(a, b, d));
+            if a != b {
+                assert!(uf.union(a, b, d + 1).is_err());
+            }
+            let (x, y) = (util::rand_upto(N), util::rand_upto(N));
+            let diff = if expected[x] == expected[y] { Some(values[x] - values[y]) } else { None };
+            assert_eq!(diff, uf.diff(x, y));
+        }
+    }
+
+    #[test]
+    fn concurrent_stress() {
+        use super::concurrent::ConcurrentUnionFind;
+        use std::thread;
+        const N: usize = 20_000;
+        const THREADS: usize = 8;
+        let unions: Vec<(usize, usize)> =
+            (0..N).map(|_| (util::rand_upto(N), util::rand_upto(N))).collect();
+        let uf = ConcurrentUnionFind::new(N);
+        thread::scope(|scope| {
+            for chunk in unions.chunks(N / THREADS) {
+                let uf = &uf;
+                scope.spawn(move || {
+                    for &(a, b) in chunk.iter() {
+                        uf.union(a, b);
+                        // a union is visible as soon as it returns
+                        assert!(uf.connected(a, b));
+                        let (x, y) = (util::rand_upto(N), util::rand_upto(N));
+                        uf.connected(x, y);
+                    }
+                });
+            }
+        });
+        // the same stream, one union at a time
+        let mut sets = pc_quick_union::CompressedQuickUnion::new(N);
+        for &(a, b) in unions.iter() {
+            sets.union(a, b);
+        }
+        assert_eq!(sets.count(), uf.count());
+        for _ in 0..N {
+            let (x, y) = (util::rand_upto(N), util::rand_upto(N));
+            assert_eq!(sets.connected(x, y), uf.connected(x, y));
+        }
+        let mut uf = uf;
+        assert_eq!(sets.groups(), DisjointSet::groups(&mut uf));
+    }
+
+    #[test]
+    fn depths() {
+        const N: usize = 100;
+        // chains for quick_union, (0, i) joins 0's whole tree each time
+        let chain: Vec<(usize, usize)> = (1..N).map(|i| (0, i)).collect();
+        fn deepest<S: DisjointSet>(unions: &[(usize, usize)]) -> usize {
+            let mut sets = S::new(N);
+            assert_eq!(0, sets.max_depth());
+            for &(a, b) in unions.iter() {
+                sets.union(a, b);
+            }
+            sets.max_depth()
+        }
+        assert_eq!(1, deepest::<quick_find::QuickFind>(&chain));
+        assert_eq!(N - 1, deepest::<quick_union::QuickUnion>(&chain));
+        assert_eq!(1, deepest::<b_quick_union::BalancedQuickUnion>(&chain));
+        assert_eq!(1, deepest::<UnionFind>(&chain));
+        assert_eq!(1, deepest::<concurrent::ConcurrentUnionFind>(&[(0, 1)]));
+        let binomial = binomial(64);
+        assert_eq!(6, deepest::<b_quick_union::RollbackUnionFind>(&binomial));
+        // its unions find the roots first, compressing as they go
+        assert!(deepest::<pc_quick_union::CompressedQuickUnion>(&binomial) < 6);
+    }
+
+    #[cfg(feature = "stats")]
+    #[test]
+    fn stats() {
+        use super::Stats;
+        const LG: usize = 10;
+        const N: usize = 1 << LG;
+        let binomial = binomial(N);
+
+        // Balanced trees never walk more than lg(n) links
+        let mut balanced = b_quick_union::BalancedQuickUnion::new(N);
+        for &(a, b) in binomial.iter() {
+            balanced.union(a, b);
+        }
+        for x in 0..N {
+            balanced.find(x);
+        }
+        let stats = balanced.stats();
+        assert_eq!((2 * (N - 1) + N) as u64, stats.finds);
+        assert_eq!(LG, stats.max_path);
+        assert_eq!(LG, stats.max_depth);
+        assert_eq!(0, stats.compressions);
+        balanced.reset_stats();
+        assert_eq!(Stats { max_depth: LG, ..Stats::default() }, balanced.stats());
+
+        // Compression flattens the tree as it is searched
+        let mut uf = UnionFind::new(N);
+        for &(a, b) in binomial.iter() {
+            uf.union(a, b);
+        }
+        assert_eq!(LG, uf.stats().max_depth);
+        uf.reset_stats();
+        uf.find(N - 1);
+        let one = uf.stats();
+        assert_eq!(Stats { finds: 1, total_path: LG as u64, max_path: LG,
+                           compressions: (LG - 1) as u64, max_depth: LG - 1 }, one);
+        for x in 0..N {
+            uf.find(x);
+        }
+        let stats = uf.stats();
+        assert_eq!(1, stats.max_depth);
+        assert!(stats.total_path < (N * LG) as u64 / 2);
+        for &compress in [Compress::Halving, Compress::Splitting].iter() {
+            let mut uf = UnionFind::with_policies(4, Link::BySize, compress);
+            uf.parent = vec![0, 0, 1, 2]; // a chain from 3 up to 0
+            uf.find(3);
+            // 3 and 2 skip a link; halving then jumps straight to 0
+            let moved = if compress == Compress::Halving { 1 } else { 2 };
+            assert_eq!(moved, uf.stats().compressions);
+        }
+
+        let mut compressed = pc_quick_union::CompressedQuickUnion::new(N);
+        for &(a, b) in binomial.iter() {
+            compressed.union(a, b);
+        }
+        for x in (0..N).rev() {
+            compressed.find(x);
+        }
+        let stats = compressed.stats();
+        assert!(stats.max_path <= LG);
+        assert!(stats.compressions > 0);
+        assert_eq!(1, stats.max_depth);
+
+        // 0 -> 1 -> 3 -> 7: the walk from 0 moves 0 and 1 up to 7, so
+        // the walk from 1 that follows is a single link
+        let mut chain = pc_quick_union::CompressedQuickUnion::new(8);
+        for &(a, b) in [(0, 1), (2, 3), (4, 5), (6, 7), (1, 3), (5, 7), (3, 7)].iter() {
+            chain.union(a, b);
+        }
+        chain.reset_stats();
+        assert!(!chain.union(0, 1));
+        assert_eq!(Stats { finds: 2, total_path: 4, max_path: 3,
+                           compressions: 2, max_depth: 2 }, chain.stats());
+    }
+
+    #[test]
+    fn keyed() {
+        let mut uf = KeyedUnionFind::new();
+        assert!(uf.is_empty());
+        assert!(uf.union("ann", "bob"));
+        assert!(uf.union("cat", "dan"));
+        assert!(uf.insert("eve"));
+        assert!(!uf.insert("ann"));
+        assert_eq!(5, uf.len());
+        assert_eq!(3, uf.count());
+        assert!(uf.connected(&"bob", &"ann"));
+        assert!(!uf.connected(&"ann", &"cat"));
+        // unseen keys are in components of their own
+        assert!(uf.connected(&"zed", &"zed"));
+        assert!(!uf.connected(&"zed", &"ann"));
+        assert!(!uf.contains(&"zed"));
+        assert_eq!(None, uf.find(&"zed"));
+        assert!(uf.component(&"zed").is_empty());
+        assert!(uf.union("dan", "bob"));
+        assert!(!uf.union("ann", "cat"));
+        assert_eq!(2, uf.count());
+        assert_eq!(vec![&"ann", &"bob", &"cat", &"dan"], uf.component(&"cat"));
+        assert_eq!(vec![&"eve"], uf.component(&"eve"));
+        let root = *uf.find(&"ann").unwrap();
+        assert_eq!(Some(&root), uf.find(&"dan"));
+
+        // coordinates work as keys too
+        let mut grid = KeyedUnionFind::new();
+        for x in 0..3 {
+            grid.union((x, 0), (x + 1, 0));
+        }
+        grid.union((5, 5), (5, 6));
+        assert!(grid.connected(&(0, 0), &(3, 0)));
+        assert!(!grid.connected(&(0, 0), &(5, 5)));
+        assert_eq!(2, grid.count());
+    }
+
+    #[test]
+    fn long_chains() {
+        // Weighting keeps the trees shallow even when every union
+        // hangs a single element off the same component
+        const N: usize = 1_000_000;
+        let mut uf = UnionFind::new(N);
+        for i in 1..N {
+            uf.union(i, i - 1);
         }
+        assert_eq!(1, uf.count());
+        assert_eq!(N, uf.component_size(N / 2));
+        assert!(uf.connected(0, N - 1));
     }
 }
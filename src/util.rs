@@ -12,7 +12,7 @@ pub fn rand_upto(n: usize) -> usize {
 
 pub fn rand_vec(n: usize) -> Vec<usize> {
     let mut v = Vec::with_capacity(n);
-    for i in 0..n {
+    for _ in 0..n {
         v.push(rand_upto(n));
     }
     v